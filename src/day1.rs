@@ -1,62 +1,407 @@
 use crate::error::Error;
+use crate::parse::parse_numbers;
+use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-
-/// Inputs the text file given by AOC and finds the solution to day 1 part 1.
-/// Outputs the (total_distance, sorted first column, sorted second column)
-pub fn d1_part1_solution(input_path: &str) -> Result<(usize, Vec<usize>, Vec<usize>), Error> {
-    let f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let reader = BufReader::new(f);
-
-    let mut c1 = Vec::new();
-    let mut c2 = Vec::new();
-    for line in reader.lines().filter_map(|line| line.ok()) {
-        // Skip the line if the line parsing fails
-        let mut s = line.split("   "); // 3 spaces
-                                       // If parsing for either number fails, skip this line
-        let first = s.next();
-        let second = s.next();
-        let s1 = first.map(|s| s.parse::<usize>());
-        let s2 = second.map(|s| s.parse::<usize>());
-        // Only push if both are good
-        if let (Some(Ok(id1)), Some(Ok(id2))) = (s1, s2) {
+use std::hash::Hash;
+use std::io::Read;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// The integer types that can be used as location ids. All arithmetic is checked, so that large
+/// ids give an error instead of a silently wrapped answer.
+pub trait LocationId:
+    Copy + Ord + Hash + Default + Display + FromStr<Err = ParseIntError>
+{
+    /// |self - other|, or None on overflow.
+    fn checked_distance(self, other: Self) -> Option<Self>;
+    fn checked_sum(self, other: Self) -> Option<Self>;
+    /// self * n, or None on overflow.
+    fn checked_mul_count(self, n: usize) -> Option<Self>;
+    fn as_f64(self) -> f64;
+}
+
+macro_rules! impl_unsigned_location_id {
+    ($($t:ty),*) => {
+        $(
+            impl LocationId for $t {
+                fn checked_distance(self, other: Self) -> Option<Self> {
+                    Some(self.abs_diff(other))
+                }
+                fn checked_sum(self, other: Self) -> Option<Self> {
+                    self.checked_add(other)
+                }
+                fn checked_mul_count(self, n: usize) -> Option<Self> {
+                    Self::try_from(n).ok().and_then(|n| self.checked_mul(n))
+                }
+                fn as_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_signed_location_id {
+    ($($t:ty),*) => {
+        $(
+            impl LocationId for $t {
+                fn checked_distance(self, other: Self) -> Option<Self> {
+                    self.checked_sub(other).and_then(|d| d.checked_abs())
+                }
+                fn checked_sum(self, other: Self) -> Option<Self> {
+                    self.checked_add(other)
+                }
+                fn checked_mul_count(self, n: usize) -> Option<Self> {
+                    Self::try_from(n).ok().and_then(|n| self.checked_mul(n))
+                }
+                fn as_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned_location_id!(usize, u64, u128);
+impl_signed_location_id!(i64);
+
+fn overflow(what: &str) -> Error {
+    Error::Other(format!("Overflow while computing the {}.", what))
+}
+
+/// The two columns of location IDs in the input, each sorted.
+pub struct LocationLists<T: LocationId> {
+    pub c1: Vec<T>,
+    pub c2: Vec<T>,
+}
+
+impl<T: LocationId> LocationLists<T> {
+    /// Parses the text given by AOC. Blank lines are skipped, and any other line must have
+    /// exactly two ids that fit in T, or an error naming the line is returned.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut c1 = Vec::new();
+        let mut c2 = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let ids = parse_numbers::<T>(line).map_err(|e| match e {
+                Error::ParseIntError(e) => Error::Other(format!(
+                    "Line {} has an id that is not a valid {} ({}): {:?}",
+                    i + 1,
+                    std::any::type_name::<T>(),
+                    e,
+                    line
+                )),
+                e => e,
+            })?;
+            let &[id1, id2] = ids.as_slice() else {
+                return Err(Error::Other(format!(
+                    "Line {} doesn't have exactly two ids: {:?}",
+                    i + 1,
+                    line
+                )));
+            };
             c1.push(id1);
             c2.push(id2);
         }
+        // sort c1 and c2
+        c1.sort_unstable();
+        c2.sort_unstable();
+
+        Ok(LocationLists { c1, c2 })
     }
-    // sort c1 and c2
-    c1.sort_unstable();
-    c2.sort_unstable();
 
-    let total_distance = c1
-        .iter()
-        .copied()
-        .zip(c2.iter().copied())
-        .fold(0usize, |acc, (x, y)| acc + (x.abs_diff(y)));
+    /// Reads the text file given by AOC, see parse.
+    pub fn load(input_path: &str) -> Result<Self, Error> {
+        let mut f = File::open(input_path).map_err(Error::IOError)?;
+        let mut buffer = String::new();
+        f.read_to_string(&mut buffer).map_err(Error::IOError)?;
+        Self::parse(&buffer)
+    }
 
-    Ok((total_distance, c1, c2))
+    /// The total distance as described in day 1 part 1.
+    pub fn total_distance(&self) -> Result<T, Error> {
+        self.c1
+            .iter()
+            .copied()
+            .zip(self.c2.iter().copied())
+            .try_fold(T::default(), |acc, (x, y)| {
+                x.checked_distance(y).and_then(|d| acc.checked_sum(d))
+            })
+            .ok_or_else(|| overflow("total distance"))
+    }
+
+    /// The similarity score as described in day 1 part 2, computed with the given strategy.
+    pub fn similarity_score(&self, strategy: SimilarityStrategy) -> Result<T, Error> {
+        let score = match strategy {
+            SimilarityStrategy::FrequencyMap => similarity_by_frequency_map(&self.c1, &self.c2),
+            SimilarityStrategy::MergeCount => similarity_by_merge_count(&self.c1, &self.c2),
+        };
+        score.ok_or_else(|| overflow("similarity score"))
+    }
 }
 
-/// Computes the similarity score as described in day 1 part 2 AOC 2024.
-pub fn d1_part2_solution(c1: Vec<usize>, c2: Vec<usize>) -> usize {
+/// Descriptive statistics of a single column.
+pub struct ColumnStats {
+    pub count: usize,
+    pub distinct: usize,
+    /// Number of ids that appear more than once.
+    pub duplicated_ids: usize,
+}
+
+/// Descriptive statistics of the two location lists.
+pub struct LocationStats<T: LocationId> {
+    pub c1: ColumnStats,
+    pub c2: ColumnStats,
+    /// Number of distinct ids that appear in both columns.
+    pub overlap: usize,
+    /// Median of the gaps between the i-th smallest ids of the two columns. None if empty.
+    pub median_gap: Option<f64>,
+    /// The largest gaps as (id in c1, id in c2, gap), largest first.
+    pub largest_gaps: Vec<(T, T, T)>,
+    /// The ids that contribute most to the similarity score as (id, contribution), largest first.
+    pub most_similar: Vec<(T, T)>,
+}
+
+/// Returns (id, number of occurrences) for a sorted column.
+fn id_counts<T: LocationId>(sorted: &[T]) -> Vec<(T, usize)> {
+    sorted
+        .chunk_by(|a, b| a == b)
+        .map(|chunk| (chunk[0], chunk.len()))
+        .collect()
+}
+
+fn column_stats<T: LocationId>(counts: &[(T, usize)]) -> ColumnStats {
+    ColumnStats {
+        count: counts.iter().map(|(_, n)| n).sum(),
+        distinct: counts.len(),
+        duplicated_ids: counts.iter().filter(|(_, n)| *n > 1).count(),
+    }
+}
+
+impl<T: LocationId> LocationLists<T> {
+    /// Computes the statistics. top_n is the length of largest_gaps and most_similar.
+    pub fn stats(&self, top_n: usize) -> Result<LocationStats<T>, Error> {
+        let counts1 = id_counts(&self.c1);
+        let counts2 = id_counts(&self.c2);
+
+        // Both count lists are sorted by id, so we can walk them together to find the overlap.
+        // Every id in both columns contributes id * (count in c1) * (count in c2) to the
+        // similarity score.
+        let mut most_similar = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < counts1.len() && j < counts2.len() {
+            let ((id1, n1), (id2, n2)) = (counts1[i], counts2[j]);
+            match id1.cmp(&id2) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    let contribution = n1
+                        .checked_mul(n2)
+                        .and_then(|n| id1.checked_mul_count(n))
+                        .ok_or_else(|| overflow("similarity of an id"))?;
+                    most_similar.push((id1, contribution));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        let overlap = most_similar.len();
+        most_similar.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        most_similar.truncate(top_n);
+
+        let mut gaps = Vec::with_capacity(self.c1.len());
+        for (x, y) in self.c1.iter().zip(self.c2.iter()) {
+            let gap = x.checked_distance(*y).ok_or_else(|| overflow("gap"))?;
+            gaps.push((*x, *y, gap));
+        }
+        // Largest first, then by ids so that ties always come out in the same order
+        gaps.sort_unstable_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+        let mid = gaps.len() / 2;
+        let median_gap = match gaps.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(gaps[mid].2.as_f64()),
+            // gaps is sorted, so the two middle elements are next to each other.
+            _ => Some((gaps[mid - 1].2.as_f64() + gaps[mid].2.as_f64()) / 2.0),
+        };
+        gaps.truncate(top_n);
+
+        Ok(LocationStats {
+            c1: column_stats(&counts1),
+            c2: column_stats(&counts2),
+            overlap,
+            median_gap,
+            largest_gaps: gaps,
+            most_similar,
+        })
+    }
+}
+
+/// How to compute the similarity score. Both give the same answer.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SimilarityStrategy {
+    /// Count every id in the second column with a HashMap, then look up the first column.
+    FrequencyMap,
+    /// Sweep both sorted columns once, like the merge step of a merge sort.
+    MergeCount,
+}
+
+/// Returns None on overflow.
+fn similarity_by_frequency_map<T: LocationId>(c1: &[T], c2: &[T]) -> Option<T> {
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    for id in c2 {
+        *counts.entry(*id).or_insert(0) += 1;
+    }
+    c1.iter().try_fold(T::default(), |acc, id| {
+        let count = counts.get(id).copied().unwrap_or(0);
+        id.checked_mul_count(count).and_then(|s| acc.checked_sum(s))
+    })
+}
+
+/// Returns None on overflow.
+fn similarity_by_merge_count<T: LocationId>(c1: &[T], c2: &[T]) -> Option<T> {
     // c1 and c2 are sorted
     let mut last_idx: usize = 0;
     // Calculate similarity score
-    c1.into_iter().fold(0usize, |acc, id| {
+    c1.iter().copied().try_fold(T::default(), |acc, id| {
         let sl = &c2[last_idx..];
-        // j = first place in sl where we have an element > id;
-        let j = sl.partition_point(|i| *i <= id);
-        // A side effect here, because the next time, we can search starting from last_idx + j.
-        last_idx += j;
-        // This implies all elements in left are <= id
-        let left = &sl[..j];
-        // Find the number of matches to id in left. Notice we can short-circuit if the
-        // last element in left is < id. If left has 0 size, we can also short-circuit.
-        if left.last().map(|l| *l < id).unwrap_or(true) {
-            acc // return acc, because we are adding 0
-        } else {
-            // The number of matches in left * id
-            acc + left.iter().rev().take_while(|&k| *k == id).count() * id
-        }
+        // start = first place in sl where we have an element >= id
+        // end = first place in sl where we have an element > id
+        let start = sl.partition_point(|i| *i < id);
+        let end = start + sl[start..].partition_point(|i| *i <= id);
+        // A side effect here, because the next time, we can search starting from last_idx + start.
+        // We don't skip past the matches, because c1 can have the same id again.
+        last_idx += start;
+        // The number of matches * id
+        id.checked_mul_count(end - start).and_then(|s| acc.checked_sum(s))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+
+    fn lists<T: LocationId>(mut c1: Vec<T>, mut c2: Vec<T>) -> LocationLists<T> {
+        c1.sort_unstable();
+        c2.sort_unstable();
+        LocationLists { c1, c2 }
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let l = lists::<u64>(vec![3, 4, 2, 1, 3, 3], vec![4, 3, 5, 3, 9, 3]);
+        assert_eq!(l.total_distance().unwrap(), 11);
+        assert_eq!(similarity_by_frequency_map(&l.c1, &l.c2), Some(31));
+        assert_eq!(similarity_by_merge_count(&l.c1, &l.c2), Some(31));
+    }
+
+    #[test]
+    fn strategies_agree_with_many_duplicates() {
+        let mut rng = Rng::new(31);
+        for _ in 0..2_000 {
+            // Few distinct ids, so that both columns repeat ids a lot
+            let n = rng.below(40);
+            let distinct = rng.range(1, 8);
+            let c1 = (0..n).map(|_| rng.range(0, distinct) as u64).collect();
+            let c2 = (0..n).map(|_| rng.range(0, distinct) as u64).collect();
+            let l = lists::<u64>(c1, c2);
+            assert_eq!(
+                similarity_by_frequency_map(&l.c1, &l.c2),
+                similarity_by_merge_count(&l.c1, &l.c2),
+                "{:?} {:?}",
+                l.c1,
+                l.c2
+            );
+        }
+    }
+
+    #[test]
+    fn u64_overflow_is_an_error() {
+        assert_eq!(u64::MAX.checked_distance(0), Some(u64::MAX));
+        assert_eq!(0u64.checked_distance(u64::MAX), Some(u64::MAX));
+        assert_eq!(u64::MAX.checked_mul_count(1), Some(u64::MAX));
+        assert_eq!(u64::MAX.checked_mul_count(2), None);
+        assert_eq!((u64::MAX / 2).checked_mul_count(2), Some(u64::MAX - 1));
+
+        // Each distance fits, but not their sum
+        let l = lists::<u64>(vec![0, 0], vec![u64::MAX, u64::MAX]);
+        assert!(matches!(l.total_distance(), Err(Error::Other(_))));
+
+        // u64::MAX appears twice in c2
+        let l = lists::<u64>(vec![u64::MAX], vec![u64::MAX, u64::MAX]);
+        for strategy in [SimilarityStrategy::FrequencyMap, SimilarityStrategy::MergeCount] {
+            assert!(matches!(l.similarity_score(strategy), Err(Error::Other(_))));
+        }
+    }
+
+    #[test]
+    fn i64_negative_ids() {
+        assert_eq!((-5i64).checked_distance(3), Some(8));
+        assert_eq!(3i64.checked_distance(-5), Some(8));
+        assert_eq!((-3i64).checked_mul_count(2), Some(-6));
+        assert_eq!(i64::MIN.checked_distance(0), None);
+        assert_eq!(i64::MIN.checked_distance(1), None);
+        assert_eq!(i64::MAX.checked_distance(-1), None);
+        assert_eq!(i64::MIN.checked_mul_count(1), Some(i64::MIN));
+        assert_eq!(i64::MIN.checked_mul_count(2), None);
+
+        let l = lists::<i64>(vec![-3, -1, 2], vec![-1, -3, -3]);
+        // Sorted: [-3, -1, 2] and [-3, -3, -1]
+        assert_eq!(l.total_distance().unwrap(), 5);
+        assert_eq!(l.similarity_score(SimilarityStrategy::FrequencyMap).unwrap(), -7);
+        assert_eq!(l.similarity_score(SimilarityStrategy::MergeCount).unwrap(), -7);
+
+        let l = lists::<i64>(vec![i64::MIN], vec![i64::MAX]);
+        assert!(matches!(l.total_distance(), Err(Error::Other(_))));
+    }
+
+    #[test]
+    fn stats_example() {
+        let l = lists::<u64>(vec![3, 4, 2, 1, 3, 3], vec![4, 3, 5, 3, 9, 3]);
+        let stats = l.stats(3).unwrap();
+        assert_eq!((stats.c1.count, stats.c1.distinct, stats.c1.duplicated_ids), (6, 4, 1));
+        assert_eq!((stats.c2.count, stats.c2.distinct, stats.c2.duplicated_ids), (6, 4, 1));
+        // 3 and 4 are in both lists
+        assert_eq!(stats.overlap, 2);
+        // The gaps are 2, 1, 0, 1, 2, 5
+        assert_eq!(stats.median_gap, Some(1.5));
+        assert_eq!(stats.largest_gaps, vec![(4, 9, 5), (1, 3, 2), (3, 5, 2)]);
+        // 3 appears 3 times in each list and 4 once, which adds up to the similarity score 31
+        assert_eq!(stats.most_similar, vec![(3, 27), (4, 4)]);
+    }
+
+    #[test]
+    fn stats_median_and_top_n() {
+        let stats = lists::<u64>(vec![1, 2, 3], vec![4, 4, 10]).stats(10).unwrap();
+        assert_eq!(stats.median_gap, Some(3.0));
+        assert_eq!(stats.largest_gaps, vec![(3, 10, 7), (1, 4, 3), (2, 4, 2)]);
+        assert_eq!(stats.overlap, 0);
+        assert!(stats.most_similar.is_empty());
+
+        let stats = lists::<u64>(vec![1, 2, 3], vec![4, 4, 10]).stats(0).unwrap();
+        assert!(stats.largest_gaps.is_empty());
+
+        let stats = lists::<u64>(vec![], vec![]).stats(3).unwrap();
+        assert_eq!(stats.median_gap, None);
+        assert_eq!(stats.c1.count, 0);
+    }
+
+    #[test]
+    fn parse_is_strict() {
+        let l = LocationLists::<u64>::parse("3   4\r\n\n  \n4\t3\n").unwrap();
+        assert_eq!((l.c1, l.c2), (vec![3, 4], vec![3, 4]));
+
+        let too_large = "3   4\n99999999999999999999999   1\n2   5\n";
+        assert!(LocationLists::<u128>::parse(too_large).is_ok());
+        for text in [too_large, "3   4\n-1   2\n", "3   4\n1   x\n", "3   4 5\n", "3\n"] {
+            match LocationLists::<u64>::parse(text) {
+                Err(Error::Other(msg)) => assert!(msg.starts_with("Line "), "{}", msg),
+                _ => panic!("expected an error for {:?}", text),
+            }
+        }
+        assert!(LocationLists::<i64>::parse("3   4\n-1   2\n").is_ok());
+    }
+}
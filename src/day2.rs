@@ -1,101 +1,469 @@
 use crate::error::Error;
+use crate::parse::parse_numbers;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::Read;
+
+/// The direction a report is allowed to move in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    Increasing,
+    Decreasing,
+    Either,
+}
+
+/// What counts as a safe report. The default is the rule from the puzzle: every step is
+/// between 1 and 3, and the report is either all increasing or all decreasing.
+#[derive(Clone, Copy, Debug)]
+pub struct SafetyRule {
+    pub min_step: i32,
+    pub max_step: i32,
+    pub allow_flat: bool,
+    pub direction: Direction,
+}
+
+impl Default for SafetyRule {
+    fn default() -> Self {
+        SafetyRule {
+            min_step: 1,
+            max_step: 3,
+            allow_flat: false,
+            direction: Direction::Either,
+        }
+    }
+}
+
+impl SafetyRule {
+    /// Returns an error if the bounds make no sense. Steps must be at least 1, since a step of 0
+    /// is only allowed with allow_flat and a negative step would go against the direction.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.min_step < 1 {
+            return Err(Error::Other(format!(
+                "The minimum step must be at least 1, got {}. Use allow_flat for flat steps.",
+                self.min_step
+            )));
+        }
+        if self.min_step > self.max_step {
+            return Err(Error::Other(format!(
+                "The minimum step {} is larger than the maximum step {}.",
+                self.min_step, self.max_step
+            )));
+        }
+        Ok(())
+    }
+
+    /// The signs to try for a report. 1 for increasing, -1 for decreasing.
+    fn signs(&self) -> &'static [i32] {
+        match self.direction {
+            Direction::Increasing => &[1],
+            Direction::Decreasing => &[-1],
+            Direction::Either => &[1, -1],
+        }
+    }
+
+    /// Whether going from a to b is a valid step, in the direction given by sign.
+    fn step_ok(&self, a: i32, b: i32, sign: i32) -> bool {
+        let diff = (b - a) * sign;
+        (self.min_step..=self.max_step).contains(&diff) || (self.allow_flat && diff == 0)
+    }
+}
 
 /// Checks the safety without dampener
-fn check_safety(data: &[i32]) -> bool {
-    if data.len() <= 1 {
-        true
-    } else {
-        let mut all_pos = true;
-        let mut all_neg = true;
-        let mut within_bound = true;
-        let mut answer = true;
-        for sl in data.array_windows::<2>() {
-            let diff = sl[1] - sl[0];
-            all_pos &= diff > 0;
-            all_neg &= diff < 0;
-            within_bound &= diff.abs() >= 1 && (diff.abs() <= 3);
-            answer = (all_pos | all_neg) & within_bound;
-            if !answer {
-                return false;
+fn check_safety(data: &[i32], rule: &SafetyRule) -> bool {
+    rule.signs().iter().any(|&sign| {
+        data.array_windows::<2>()
+            .all(|[a, b]| rule.step_ok(*a, *b, sign))
+    })
+}
+
+/// Returns the index of the first window (data[i], data[i + 1]) whose step breaks the rule in
+/// the direction given by sign (1 for increasing, -1 for decreasing), skipping the level at skip.
+fn first_bad_window(
+    data: &[i32],
+    rule: &SafetyRule,
+    sign: i32,
+    skip: Option<usize>,
+) -> Option<usize> {
+    let mut prev: Option<(usize, i32)> = None;
+    for (i, &x) in data.iter().enumerate() {
+        if Some(i) == skip {
+            continue;
+        }
+        if let Some((j, p)) = prev {
+            if !rule.step_ok(p, x, sign) {
+                return Some(j);
             }
         }
-        answer
+        prev = Some((i, x));
     }
+    None
+}
+
+/// Finds the level that, once removed, makes data safe in the direction given by sign.
+/// Returns None if data is already safe in that direction or if no single removal helps.
+///
+/// If the first bad window is (data[i], data[j]), that window survives any removal other than
+/// i or j, so those are the only two candidates worth checking. Each check is one pass,
+/// which makes the whole thing O(n) without copying data.
+fn removable_level(data: &[i32], rule: &SafetyRule, sign: i32) -> Option<usize> {
+    let i = first_bad_window(data, rule, sign, None)?;
+    // The first bad window always starts at i and ends at i + 1 when nothing is skipped.
+    [i, i + 1]
+        .into_iter()
+        .find(|&k| first_bad_window(data, rule, sign, Some(k)).is_none())
 }
 
-/// Checks the safety wit dampener
-fn check_safety_with_dampener(data: &[i32]) -> bool {
-    // Kind of a brute-force approach, but I try my best to reduce copying data
-    // The reason I don't use a linked-list is that...
-    // Vec is faster for most of the tasks. Copying small vecs is very fast.
+/// Returns the minimum number of levels to remove so that data becomes safe.
+///
+/// This is the length of data minus the longest subsequence that is safe, found with an O(n^2)
+/// DP over the directions allowed by rule. best[i] is the longest safe subsequence ending at
+/// data[i].
+fn min_removals(data: &[i32], rule: &SafetyRule) -> usize {
     if data.len() <= 1 {
-        true
-    } else {
-        // Note: with the dampener, if data[1..] is safe in the og sense, then data is safe
-        // in the dampener sense. All data that is safe in the og sense will also be captured
-        // here in this first branch
-        if check_safety(&data[1..]) {
-            return true;
-        } else {
-            // Now check what happens if we remove the j-th number in the og data.
-            for j in 1..data.len() {
-                let mut new_data = vec![0i32; data.len() - 1];
-                for i in 0..j {
-                    new_data[i] = data[i];
-                }
-                for i in j..new_data.len() {
-                    new_data[i] = data[i + 1];
-                }
-                if check_safety(&new_data) {
-                    return true;
+        return 0;
+    }
+    let mut longest = 1usize;
+    let mut best = vec![1usize; data.len()];
+    for &sign in rule.signs() {
+        for i in 0..data.len() {
+            best[i] = 1;
+            for j in 0..i {
+                if rule.step_ok(data[j], data[i], sign) {
+                    best[i] = best[i].max(best[j] + 1);
                 }
             }
-            // Finished for loop without returning, false
-            false
+            longest = longest.max(best[i]);
         }
     }
+    data.len() - longest
 }
 
-/// Parses the line, and returns a ParseError if get an error during parsing.
-fn parse_line(line: String) -> Result<Vec<i32>, Error> {
-    let mut numbers = Vec::new();
-    for v in line.split(' ') {
-        match v.parse::<i32>() {
-            Ok(i) => numbers.push(i),
-            Err(e) => return Err(Error::ParseIntError(e)),
+/// Checks the safety with a dampener that tolerates removing up to k levels.
+fn check_safety_with_dampener(data: &[i32], k: usize, rule: &SafetyRule) -> bool {
+    match k {
+        0 => check_safety(data, rule),
+        // The common case of the puzzle has a linear time check.
+        1 => {
+            data.len() <= 2
+                || rule.signs().iter().any(|&sign| {
+                    first_bad_window(data, rule, sign, None).is_none()
+                        || removable_level(data, rule, sign).is_some()
+                })
         }
+        _ => min_removals(data, rule) <= k,
     }
-    Ok(numbers)
+}
+
+/// Why a window of a report breaks the safety rule.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnsafeReason {
+    DirectionChange,
+    StepTooLarge,
+    StepTooSmall,
+    FlatStep,
+}
+
+impl std::fmt::Display for UnsafeReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            UnsafeReason::DirectionChange => "direction change",
+            UnsafeReason::StepTooLarge => "step too large",
+            UnsafeReason::StepTooSmall => "step too small",
+            UnsafeReason::FlatStep => "flat step",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The diagnostic result for a single report.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Diagnosis {
+    Safe,
+    /// Safe once the level at removed is taken out by the dampener.
+    SafeWithDampener { removed: usize },
+    /// Unsafe even with the dampener. window is the index of the first offending window
+    /// (data[window], data[window + 1]).
+    Unsafe { window: usize, reason: UnsafeReason },
+}
+
+/// Diagnoses a report under rule, with a dampener that tolerates one bad level.
+fn diagnose(data: &[i32], rule: &SafetyRule) -> Diagnosis {
+    // The report is expected to keep going the way its first non-flat step goes.
+    let sign = match rule.direction {
+        Direction::Increasing => 1,
+        Direction::Decreasing => -1,
+        Direction::Either => data
+            .array_windows::<2>()
+            .map(|[a, b]| (b - a).signum())
+            .find(|s| *s != 0)
+            // Only flat steps, both directions are the same
+            .unwrap_or(1),
+    };
+    // A report that is safe in a direction has its first non-flat step in that direction, so
+    // checking sign is the same as check_safety.
+    let Some(window) = first_bad_window(data, rule, sign, None) else {
+        return Diagnosis::Safe;
+    };
+    if let Some(removed) = rule
+        .signs()
+        .iter()
+        .find_map(|&sign| removable_level(data, rule, sign))
+    {
+        return Diagnosis::SafeWithDampener { removed };
+    }
+
+    let diff = (data[window + 1] - data[window]) * sign;
+    let reason = if diff == 0 {
+        UnsafeReason::FlatStep
+    } else if diff < 0 {
+        UnsafeReason::DirectionChange
+    } else if diff > rule.max_step {
+        UnsafeReason::StepTooLarge
+    } else {
+        UnsafeReason::StepTooSmall
+    };
+    Diagnosis::Unsafe { window, reason }
 }
 
 pub fn d2_part1_solution(input_path: &str) -> Result<usize, Error> {
-    let f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let reader = BufReader::new(f);
+    d2_count_with_dampener(input_path, 0, &SafetyRule::default())
+}
 
-    let mut count: usize = 0;
-    for line in reader.lines().filter_map(|line| line.ok()) {
-        // Split line into Vec of numbers. Only use lines we parse successfully
-        if let Ok(v) = parse_line(line) {
-            count += check_safety(&v) as usize;
+pub fn d2_part2_solution(input_path: &str) -> Result<usize, Error> {
+    d2_count_with_dampener(input_path, 1, &SafetyRule::default())
+}
+
+/// Parses one report per line. Blank lines are skipped, and any other line that is not a list of
+/// numbers gives a ParseIntError.
+fn parse_reports(text: &str) -> Result<Vec<Vec<i32>>, Error> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_numbers::<i32>)
+        .collect()
+}
+
+/// Loads every report in the input.
+fn load_reports(input_path: &str) -> Result<Vec<Vec<i32>>, Error> {
+    let mut f = File::open(input_path).map_err(Error::IOError)?;
+    let mut buffer = String::new();
+    f.read_to_string(&mut buffer).map_err(Error::IOError)?;
+    parse_reports(&buffer)
+}
+
+/// Counts the reports that are safe under rule when the dampener tolerates up to k bad levels.
+pub fn d2_count_with_dampener(
+    input_path: &str,
+    k: usize,
+    rule: &SafetyRule,
+) -> Result<usize, Error> {
+    rule.validate()?;
+    let reports = load_reports(input_path)?;
+    Ok(reports
+        .iter()
+        .filter(|v| check_safety_with_dampener(v, k, rule))
+        .count())
+}
+
+/// Returns the histogram of reports by the minimum number of removals needed to make them safe
+/// under rule.
+/// The i-th element is the number of reports needing exactly i removals.
+fn removal_histogram(reports: &[Vec<i32>], rule: &SafetyRule) -> Vec<usize> {
+    let mut histogram = Vec::new();
+    for v in reports {
+        let k = min_removals(v, rule);
+        if histogram.len() <= k {
+            histogram.resize(k + 1, 0);
         }
+        histogram[k] += 1;
     }
+    histogram
+}
 
-    Ok(count)
+/// Like removal_histogram, for the reports in the input.
+pub fn d2_removal_histogram(input_path: &str, rule: &SafetyRule) -> Result<Vec<usize>, Error> {
+    rule.validate()?;
+    let reports = load_reports(input_path)?;
+    Ok(removal_histogram(&reports, rule))
 }
 
-pub fn d2_part2_solution(input_path: &str) -> Result<usize, Error> {
-    let f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let reader = BufReader::new(f);
+/// Returns every report together with its diagnosis under rule, in input order.
+pub fn d2_diagnostics(
+    input_path: &str,
+    rule: &SafetyRule,
+) -> Result<Vec<(Vec<i32>, Diagnosis)>, Error> {
+    rule.validate()?;
+    let reports = load_reports(input_path)?;
+    Ok(reports
+        .into_iter()
+        .map(|v| {
+            let d = diagnose(&v, rule);
+            (v, d)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+
+    /// The removal loop this module used before the linear check, kept to check against.
+    fn brute_force_dampener(data: &[i32], rule: &SafetyRule) -> bool {
+        check_safety(data, rule)
+            || (0..data.len()).any(|j| {
+                let mut new_data = data.to_vec();
+                new_data.remove(j);
+                check_safety(&new_data, rule)
+            })
+    }
+
+    /// Reports of length 0 to 8 whose steps are mostly small, so that flat steps, direction
+    /// changes and single bad levels are all common.
+    fn random_report(rng: &mut Rng) -> Vec<i32> {
+        let len = rng.below(9);
+        let mut level = rng.range(0, 20) as i32;
+        let mut report = Vec::with_capacity(len);
+        for _ in 0..len {
+            report.push(level);
+            level += rng.range(-4, 4) as i32;
+        }
+        report
+    }
+
+    /// The default rule, and rules that change how removable_level and diagnose work.
+    fn rules() -> [SafetyRule; 4] {
+        let default = SafetyRule::default();
+        [
+            default,
+            SafetyRule { allow_flat: true, ..default },
+            SafetyRule { direction: Direction::Increasing, ..default },
+            SafetyRule { min_step: 2, max_step: 4, direction: Direction::Decreasing, ..default },
+        ]
+    }
+
+    #[test]
+    fn linear_dampener_matches_brute_force() {
+        let mut rng = Rng::new(26);
+        for _ in 0..100_000 {
+            let report = random_report(&mut rng);
+            for rule in rules() {
+                assert_eq!(
+                    check_safety_with_dampener(&report, 1, &rule),
+                    brute_force_dampener(&report, &rule),
+                    "{:?} {:?}",
+                    report,
+                    rule
+                );
+            }
+        }
+    }
 
-    let mut count: usize = 0;
-    for line in reader.lines().filter_map(|line| line.ok()) {
-        // Split line into Vec of numbers. Only use lines we parse successfully
-        if let Ok(v) = parse_line(line) {
-            count += check_safety_with_dampener(&v) as usize;
+    #[test]
+    fn bad_bounds_are_rejected() {
+        let default = SafetyRule::default();
+        for rule in rules() {
+            assert!(rule.validate().is_ok());
+        }
+        for (min_step, max_step) in [(0, 3), (-1, 3), (4, 3)] {
+            let rule = SafetyRule { min_step, max_step, ..default };
+            assert!(matches!(rule.validate(), Err(Error::Other(_))), "{:?}", rule);
+        }
+    }
+
+    #[test]
+    fn short_reports_are_safe_with_dampener() {
+        let rule = SafetyRule::default();
+        for report in [vec![], vec![5], vec![5, 5], vec![1, 9], vec![1, 9, 1], vec![3, 3, 3]] {
+            assert_eq!(
+                check_safety_with_dampener(&report, 1, &rule),
+                brute_force_dampener(&report, &rule),
+                "{:?}",
+                report
+            );
+        }
+    }
+
+    #[test]
+    fn diagnose_matches_checks() {
+        let mut rng = Rng::new(29);
+        for _ in 0..50_000 {
+            let report = random_report(&mut rng);
+            for rule in rules() {
+                let expected = if check_safety(&report, &rule) {
+                    "safe"
+                } else if check_safety_with_dampener(&report, 1, &rule) {
+                    "dampener"
+                } else {
+                    "unsafe"
+                };
+                let found = match diagnose(&report, &rule) {
+                    Diagnosis::Safe => "safe",
+                    Diagnosis::SafeWithDampener { .. } => "dampener",
+                    Diagnosis::Unsafe { .. } => "unsafe",
+                };
+                assert_eq!(found, expected, "{:?} {:?}", report, rule);
+            }
         }
     }
 
-    Ok(count)
+    /// The fewest levels to remove so that data is safe, trying every subset.
+    fn brute_force_min_removals(data: &[i32], rule: &SafetyRule) -> usize {
+        (0u32..1 << data.len())
+            .filter(|removed| {
+                let kept = (0..data.len())
+                    .filter(|i| removed & (1 << i) == 0)
+                    .map(|i| data[i])
+                    .collect::<Vec<_>>();
+                check_safety(&kept, rule)
+            })
+            .map(|removed| removed.count_ones() as usize)
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn min_removals_matches_brute_force() {
+        let mut rng = Rng::new(27);
+        for _ in 0..10_000 {
+            let report = random_report(&mut rng);
+            for rule in rules() {
+                let k = min_removals(&report, &rule);
+                assert_eq!(k, brute_force_min_removals(&report, &rule), "{:?} {:?}", report, rule);
+                assert_eq!(k == 0, check_safety(&report, &rule), "{:?} {:?}", report, rule);
+                assert_eq!(
+                    k <= 1,
+                    check_safety_with_dampener(&report, 1, &rule),
+                    "{:?} {:?}",
+                    report,
+                    rule
+                );
+                for tolerance in 2..4 {
+                    let safe = check_safety_with_dampener(&report, tolerance, &rule);
+                    assert_eq!(k <= tolerance, safe, "{:?} {:?}", report, rule);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn removal_histogram_example() {
+        let reports = [
+            vec![7, 6, 4, 2, 1],
+            vec![1, 2, 7, 8, 9],
+            vec![9, 7, 6, 2, 1],
+            vec![1, 3, 2, 4, 5],
+            vec![8, 6, 4, 4, 1],
+            vec![1, 3, 6, 7, 9],
+        ];
+        // 1 2 7 8 9 keeps 7 8 9, and 9 7 6 2 1 keeps 9 7 6
+        assert_eq!(removal_histogram(&reports, &SafetyRule::default()), vec![2, 2, 2]);
+        assert!(removal_histogram(&[], &SafetyRule::default()).is_empty());
+    }
+
+    #[test]
+    fn parse_reports_is_strict() {
+        let reports = parse_reports("7 6 4\r\n\n  \n1\t2  3 \n").unwrap();
+        assert_eq!(reports, vec![vec![7, 6, 4], vec![1, 2, 3]]);
+        assert!(matches!(parse_reports("7 6 4\n1 2 x\n"), Err(Error::ParseIntError(_))));
+        assert!(matches!(parse_reports("1,2,3"), Err(Error::ParseIntError(_))));
+    }
 }
@@ -1,8 +1,10 @@
 use crate::error::Error;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 
-pub enum Direction<const N: usize> {
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
     TopLeft,
     Top,
     TopRight,
@@ -13,9 +15,22 @@ pub enum Direction<const N: usize> {
     BottomRight
 }
 
-impl <const N: usize> Direction<N> {
-
-    fn step(&self, i:usize, j:usize) -> Option<(usize, usize)> {
+impl Direction {
+
+    pub const ALL: [Direction; 8] = [
+        Direction::TopLeft,
+        Direction::Top,
+        Direction::TopRight,
+        Direction::Left,
+        Direction::Right,
+        Direction::BottomLeft,
+        Direction::Bottom,
+        Direction::BottomRight,
+    ];
+
+    /// Steps from (i, j) in a grid with the given number of rows and columns.
+    /// Returns None if the step goes out of the grid.
+    fn step(&self, i:usize, j:usize, rows:usize, cols:usize) -> Option<(usize, usize)> {
         match self {
             Direction::TopLeft => {
                 if i >= 1 && j >= 1 {
@@ -32,7 +47,7 @@ impl <const N: usize> Direction<N> {
                 }
             },
             Direction::TopRight => {
-                if i >= 1 && j + 1 < N {
+                if i >= 1 && j + 1 < cols {
                     Some((i - 1, j + 1))
                 } else {
                     None
@@ -46,28 +61,28 @@ impl <const N: usize> Direction<N> {
                 }
             },
             Direction::Right => {
-                if j + 1 < N {
+                if j + 1 < cols {
                     Some((i, j + 1))
                 } else {
                     None
                 }
             },
             Direction::BottomLeft => {
-                if i + 1 < N && j >= 1 {
+                if i + 1 < rows && j >= 1 {
                     Some((i + 1, j - 1))
                 } else {
                     None
                 }
             },
             Direction::Bottom => {
-                if i + 1 < N {
+                if i + 1 < rows {
                     Some((i + 1, j))
                 } else {
                     None
                 }
             },
             Direction::BottomRight => {
-                if i + 1 < N && j + 1 < N {
+                if i + 1 < rows && j + 1 < cols {
                     Some((i + 1, j + 1))
                 } else {
                     None
@@ -81,185 +96,458 @@ impl <const N: usize> Direction<N> {
 
 }
 
-#[derive(Clone, Copy, PartialEq)]
-enum XMAS {
-    X, 
-    M,
-    A,
-    S,
-    None
+/// A rectangular grid of characters.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Vec<char>>,
 }
 
-impl From<char> for XMAS {
-    fn from(value: char) -> Self {
-        match value {
-            'X' => Self::X,
-            'M' => Self::M,
-            'A' => Self::A,
-            'S' => Self::S,
-            _ => Self::None
+impl Grid {
+
+    /// Parses the lines of text into a grid. Trailing whitespace and blank lines are ignored.
+    /// Returns an error if the lines don't all have the same length.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let cells = text
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let rows = cells.len();
+        let cols = cells.first().map(|row| row.len()).unwrap_or(0);
+        if let Some(i) = cells.iter().position(|row| row.len() != cols) {
+            return Err(Error::Other(format!(
+                "Grid is not rectangular. Row {} has length {}, expected {}.",
+                i, cells[i].len(), cols
+            )));
         }
+        Ok(Grid { rows, cols, cells })
     }
-}
 
-impl XMAS {
+    fn get(&self, i: usize, j: usize) -> Option<char> {
+        self.cells.get(i).and_then(|row| row.get(j)).copied()
+    }
 
-    fn next(&self) -> Self {
-        match self {
-            XMAS::X => Self::M,
-            XMAS::M => Self::A,
-            XMAS::A => Self::S,
-            _ => Self::None 
-        }
+    fn step(&self, i: usize, j: usize, direction: Direction) -> Option<(usize, usize)> {
+        direction.step(i, j, self.rows, self.cols)
     }
 
-    fn prev(&self) -> Self {
-        match self {
-            XMAS::M => Self::X,
-            XMAS::A => Self::M,
-            XMAS::S => Self::A,
-            _ => Self::None 
-        }
+    /// Rotates the grid by 90 degrees clockwise.
+    fn rotate(&self) -> Self {
+        let cells = (0..self.cols)
+            .map(|j| (0..self.rows).rev().map(|i| self.cells[i][j]).collect())
+            .collect();
+        Grid { rows: self.cols, cols: self.rows, cells }
     }
 
-    fn is_final(&self) -> bool {
-        match self {
-            XMAS::S => true,
-            _ => false
+    /// Mirrors the grid left to right.
+    fn reflect(&self) -> Self {
+        let cells = self
+            .cells
+            .iter()
+            .map(|row| row.iter().rev().copied().collect())
+            .collect();
+        Grid { rows: self.rows, cols: self.cols, cells }
+    }
+
+    /// All the distinct rotations and reflections of the grid, starting with the grid itself.
+    pub fn symmetries(&self) -> Vec<Grid> {
+        let mut variants: Vec<Grid> = Vec::with_capacity(8);
+        let mut current = self.clone();
+        for _ in 0..4 {
+            for variant in [current.reflect(), current.clone()] {
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+            }
+            current = current.rotate();
         }
+        // Keep the grid itself first
+        if let Some(k) = variants.iter().position(|v| v == self) {
+            variants.swap(0, k);
+        }
+        variants
     }
 }
 
-
-
-// I know the map is size 140x140
-fn load_map(data_string: String) -> [[XMAS; 140]; 140] {
-    let mut data_map: [[XMAS; 140]; 140] = [[XMAS::None; 140]; 140];
-    for (i, line) in data_string.lines().enumerate().take(140) {
-        for (j, char) in line.chars().enumerate().take(140) {
-            data_map[i][j] = char.into();
-        }        
-    }
-    data_map
+/// A word found in the grid. start is the position of the first letter.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WordMatch {
+    pub start: (usize, usize),
+    pub direction: Direction,
 }
 
-fn get_target_coords(map: &[[XMAS; 140]; 140], target: XMAS) -> Vec<(usize, usize, XMAS)> {
+fn get_target_coords(grid: &Grid, target: char) -> Vec<(usize, usize)> {
     let mut matches = Vec::new();
-    for i in 0..140 {
-        for j in 0..140 {
-            if map[i][j] == target {
-                matches.push((i, j, target));
+    for i in 0..grid.rows {
+        for j in 0..grid.cols {
+            if grid.get(i, j) == Some(target) {
+                matches.push((i, j));
             }
         }
     }
     matches
 }
 
-/// Given the center (i, j), go through each of its 8 directions to find XMAS
-fn check_xmas_in_direction(
-    map:&[[XMAS;140];140], 
-    i:usize, 
-    j:usize,
-    direction: Direction<140>
+/// Given the start (i, j), which holds the first letter of word, check if the rest of word
+/// follows in direction.
+fn check_word_in_direction(
+    grid: &Grid,
+    i: usize,
+    j: usize,
+    word: &[char],
+    direction: Direction
 ) -> bool {
 
-    let mut start = map[i][j];
     let mut x = i;
     let mut y = j;
-    for _ in 0..3 { // Move 3 more steps, because we start with X
-        let next = start.next();
-        if let Some((u, v)) = direction.step(x, y) {
-            if map[u][v] == next {
-                start = next;
+    for &next in &word[1..] {
+        match grid.step(x, y, direction) {
+            Some((u, v)) if grid.get(u, v) == Some(next) => {
                 x = u;
                 y = v;
-            } else {
-                return false
             }
-        } else {
-            return false
+            _ => return false
         }
     }
+    true
+}
+
+/// Finds every occurrence of word in the grid going in one of the directions.
+pub fn word_search(grid: &Grid, word: &str, directions: &[Direction]) -> Vec<WordMatch> {
+    let word = word.chars().collect::<Vec<_>>();
+    let Some(&first) = word.first() else {
+        return Vec::new();
+    };
 
-    start.is_final()
+    let mut matches = Vec::new();
+    for (i, j) in get_target_coords(grid, first) {
+        for &direction in directions {
+            if check_word_in_direction(grid, i, j, &word, direction) {
+                matches.push(WordMatch { start: (i, j), direction });
+            }
+        }
+    }
+    matches
 }
 
-/// Given the center (i, j), which is A, check if both the diagonals of the 3x3 square qualify
-fn check_mas_in_square(
-    map:&[[XMAS;140];140], 
-    i:usize, 
-    j:usize,
-) -> bool {
+/// The character that matches anything in a stencil.
+pub const WILDCARD: char = '.';
 
-    let top_left = Direction::<140>::TopLeft;
-    let top_right = Direction::<140>::TopRight;
-    let bottom_left = Direction::<140>::BottomLeft;
-    let bottom_right = Direction::<140>::BottomRight;
+/// The X-MAS of part 2. The other ways to write it are rotations of this one.
+pub const X_MAS: &str = "M.S\n.A.\nM.S";
 
-    let mut count= 0usize;
+/// A stencil found in the grid. variant is the index of the rotation or reflection that matched,
+/// in the order of Grid::symmetries, and top_left is where its top left corner is in the grid.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StencilMatch {
+    pub top_left: (usize, usize),
+    pub variant: usize,
+}
+
+/// Parses a stencil. Returns an error if it has no cell other than WILDCARD, since it would
+/// match everywhere.
+fn parse_stencil(text: &str) -> Result<Grid, Error> {
+    let stencil = Grid::parse(text)?;
+    if stencil.cells.iter().flatten().all(|&c| c == WILDCARD) {
+        return Err(Error::Other(format!(
+            "Stencil {:?} has no cell other than the wildcard '{}'.",
+            text, WILDCARD
+        )));
+    }
+    Ok(stencil)
+}
+
+/// Whether the stencil matches the grid with its top left corner at (i, j).
+fn check_stencil_at(grid: &Grid, stencil: &Grid, i: usize, j: usize) -> bool {
+    stencil.cells.iter().enumerate().all(|(u, row)| {
+        row.iter()
+            .enumerate()
+            .all(|(v, &c)| c == WILDCARD || grid.get(i + u, j + v) == Some(c))
+    })
+}
 
-    let letter_top_left = top_left.step(i, j);
-    let letter_bottom_right = bottom_right.step(i, j);
-    if let (Some((i, j)), Some((u, v))) = (letter_top_left, letter_bottom_right) {
-        if (map[i][j] == XMAS::M && map[u][v] == XMAS::S) || (map[i][j] == XMAS::S && map[u][v] == XMAS::M) {
-            count += 1;
+/// Finds every position where the stencil, or one of its rotations and reflections, matches the
+/// grid. Rotations and reflections that give the same stencil are only tried once.
+pub fn stencil_search(grid: &Grid, stencil: &Grid) -> Vec<StencilMatch> {
+    let mut matches = Vec::new();
+    for (variant, s) in stencil.symmetries().iter().enumerate() {
+        if s.rows > grid.rows || s.cols > grid.cols {
+            continue;
+        }
+        for i in 0..=grid.rows - s.rows {
+            for j in 0..=grid.cols - s.cols {
+                if check_stencil_at(grid, s, i, j) {
+                    matches.push(StencilMatch { top_left: (i, j), variant });
+                }
+            }
         }
     }
+    matches
+}
 
-    let letter_bottom_left = bottom_left.step(i, j);
-    let letter_top_right = top_right.step(i, j);
-    if let (Some((i, j)), Some((u, v))) = (letter_bottom_left, letter_top_right) {
-        if (map[i][j] == XMAS::M && map[u][v] == XMAS::S) || (map[i][j] == XMAS::S && map[u][v] == XMAS::M) {
-            count += 1;
+/// ANSI colours for the highlighted cells, one per direction (in the order of Direction::ALL) or
+/// per stencil variant.
+const COLORS: [&str; 8] = [
+    "\x1b[31m", "\x1b[32m", "\x1b[33m", "\x1b[34m", "\x1b[35m", "\x1b[36m", "\x1b[91m", "\x1b[92m",
+];
+const RESET: &str = "\x1b[0m";
+
+/// What to highlight when rendering the grid.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderTarget {
+    /// The XMAS words of part 1, coloured by direction.
+    Words,
+    /// The X-MAS crosses of part 2, coloured by rotation.
+    Crosses,
+}
+
+/// The cells covered by a word match of the given length.
+fn word_cells(grid: &Grid, m: &WordMatch, len: usize) -> Vec<(usize, usize)> {
+    let mut cells = vec![m.start];
+    let (mut x, mut y) = m.start;
+    for _ in 1..len {
+        match grid.step(x, y, m.direction) {
+            Some((u, v)) => {
+                cells.push((u, v));
+                x = u;
+                y = v;
+            }
+            None => break,
         }
     }
+    cells
+}
 
-    count >= 2
+/// The cells covered by the non wildcard characters of a stencil match.
+fn stencil_cells(variant: &Grid, m: &StencilMatch) -> Vec<(usize, usize)> {
+    let (i, j) = m.top_left;
+    let mut cells = Vec::new();
+    for (u, row) in variant.cells.iter().enumerate() {
+        for (v, &c) in row.iter().enumerate() {
+            if c != WILDCARD {
+                cells.push((i + u, j + v));
+            }
+        }
+    }
+    cells
 }
 
+/// Prints the grid with every character that is not part of a match replaced by '.'.
+/// marks[i][j] is the colour index of the match covering (i, j), if any.
+fn render(grid: &Grid, marks: &[Vec<Option<usize>>], color: bool) -> String {
+    let mut out = String::with_capacity(grid.rows * (grid.cols + 1));
+    for (row, row_marks) in grid.cells.iter().zip(marks) {
+        for (&c, mark) in row.iter().zip(row_marks) {
+            match mark {
+                Some(k) if color => {
+                    out.push_str(COLORS[k % COLORS.len()]);
+                    out.push(c);
+                    out.push_str(RESET);
+                }
+                Some(_) => out.push(c),
+                None => out.push('.'),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
 
-pub fn d4_part1_solution(input_path: &str) -> Result<usize, Error> {
-    let mut f = File::open(input_path).map_err(|e| Error::IOError(e))?;
+fn load_grid(input_path: &str) -> Result<Grid, Error> {
+    let mut f = File::open(input_path).map_err(Error::IOError)?;
     let mut buffer = String::new();
-    f.read_to_string(&mut buffer).map_err(|e| Error::IOError(e))?;
-    let map = load_map(buffer);
-    let search_coords = get_target_coords(&map, XMAS::X);
-    
-    let result = search_coords
-        .into_iter()
-        .fold(0usize, |acc, coord| {
-            let (i, j, _) = coord;
-            acc + (
-                check_xmas_in_direction(&map, i, j, Direction::Bottom) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::BottomLeft) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::BottomRight) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::Left) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::Right) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::TopLeft) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::Top) as usize
-                + check_xmas_in_direction(&map, i, j, Direction::TopRight) as usize
-            )
-        });
-    Ok(result)
-    
+    f.read_to_string(&mut buffer).map_err(Error::IOError)?;
+    Grid::parse(&buffer)
+}
 
+pub fn d4_part1_solution(input_path: &str) -> Result<usize, Error> {
+    let grid = load_grid(input_path)?;
+    Ok(word_search(&grid, "XMAS", &Direction::ALL).len())
 }
 
 
 pub fn d4_part2_solution(input_path: &str) -> Result<usize, Error> {
-    let mut f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let mut buffer = String::new();
-    f.read_to_string(&mut buffer).map_err(|e| Error::IOError(e))?;
-    let map = load_map(buffer);
-    let search_coords = get_target_coords(&map, XMAS::A);
-    
-    let result = search_coords
-        .into_iter()
-        .fold(0usize, |acc, coord| {
-            let (i, j, _) = coord;
-            acc + check_mas_in_square(&map, i, j) as usize
-        });
-    Ok(result)
-    
-
-}
\ No newline at end of file
+    d4_count_stencil(input_path, X_MAS)
+}
+
+/// Counts the matches of the stencil given as text, with one line per row.
+pub fn d4_count_stencil(input_path: &str, stencil: &str) -> Result<usize, Error> {
+    let grid = load_grid(input_path)?;
+    let stencil = parse_stencil(stencil)?;
+    Ok(stencil_search(&grid, &stencil).len())
+}
+
+/// Renders the grid like the puzzle illustrations, keeping only the characters of the matches.
+/// With color, every cell of a match is coloured by the direction (or rotation) of the match.
+pub fn render_matches(grid: &Grid, target: RenderTarget, color: bool) -> Result<String, Error> {
+    let mut marks = vec![vec![None; grid.cols]; grid.rows];
+    match target {
+        RenderTarget::Words => {
+            for m in word_search(grid, "XMAS", &Direction::ALL) {
+                let k = Direction::ALL.iter().position(|d| *d == m.direction);
+                for (i, j) in word_cells(grid, &m, 4) {
+                    marks[i][j] = k;
+                }
+            }
+        }
+        RenderTarget::Crosses => {
+            let stencil = parse_stencil(X_MAS)?;
+            let variants = stencil.symmetries();
+            for m in stencil_search(grid, &stencil) {
+                for (i, j) in stencil_cells(&variants[m.variant], &m) {
+                    marks[i][j] = Some(m.variant);
+                }
+            }
+        }
+    }
+    Ok(render(grid, &marks, color))
+}
+
+/// Renders the matches in the grid of the input file, see render_matches.
+pub fn d4_render(input_path: &str, target: RenderTarget, color: bool) -> Result<String, Error> {
+    render_matches(&load_grid(input_path)?, target, color)
+}
+
+/// The matches of a word broken down by direction.
+pub struct DirectionBreakdown {
+    /// The number of matches going in each direction, in the order of Direction::ALL.
+    pub counts: Vec<(Direction, usize)>,
+    /// Whether the word reads the same backwards. Then every match is also found going the
+    /// opposite way, and the total counts every occurrence twice.
+    pub palindrome: bool,
+    /// The number of pairs of matches that cover exactly the same cells.
+    pub same_cell_pairs: usize,
+}
+
+/// Searches word in all 8 directions and breaks the matches down by direction.
+pub fn direction_breakdown(grid: &Grid, word: &str) -> DirectionBreakdown {
+    let len = word.chars().count();
+    let matches = word_search(grid, word, &Direction::ALL);
+
+    let counts = Direction::ALL
+        .iter()
+        .map(|d| (*d, matches.iter().filter(|m| m.direction == *d).count()))
+        .collect();
+
+    // Two matches cover the same cells when one is the other read backwards.
+    let mut by_cells: HashMap<Vec<(usize, usize)>, usize> = HashMap::new();
+    for m in &matches {
+        let mut cells = word_cells(grid, m, len);
+        cells.sort_unstable();
+        *by_cells.entry(cells).or_insert(0) += 1;
+    }
+    let same_cell_pairs = by_cells.values().map(|n| n * (n - 1) / 2).sum();
+
+    DirectionBreakdown {
+        counts,
+        palindrome: word.chars().eq(word.chars().rev()),
+        same_cell_pairs,
+    }
+}
+
+/// Breaks down the matches of word in the grid of the input file, see direction_breakdown.
+pub fn d4_direction_breakdown(input_path: &str, word: &str) -> Result<DirectionBreakdown, Error> {
+    Ok(direction_breakdown(&load_grid(input_path)?, word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "MMMSXXMASM
+MSAMXMSMSA
+AMXSXMAAMM
+MSAMASMSMX
+XMASAMXAMM
+XXAMMXXAMA
+SMSMSASXSS
+SAXAMASAAA
+MAMMMXMMMM
+MXMXAXMASX
+";
+
+    #[test]
+    fn word_search_example() {
+        let grid = Grid::parse(EXAMPLE).unwrap();
+        assert_eq!(word_search(&grid, "XMAS", &Direction::ALL).len(), 18);
+    }
+
+    #[test]
+    fn stencil_search_example() {
+        let grid = Grid::parse(EXAMPLE).unwrap();
+        let stencil = parse_stencil(X_MAS).unwrap();
+        assert_eq!(stencil_search(&grid, &stencil).len(), 9);
+    }
+
+    #[test]
+    fn x_mas_has_four_variants() {
+        // The reflections of X-MAS are also rotations of it
+        let variants = parse_stencil(X_MAS).unwrap().symmetries();
+        assert_eq!(variants.len(), 4);
+        assert_eq!(variants[0], Grid::parse(X_MAS).unwrap());
+    }
+
+    #[test]
+    fn render_matches_example() {
+        let grid = Grid::parse(EXAMPLE).unwrap();
+        // The illustrations of the puzzle
+        let words = "\
+....XXMAS.
+.SAMXMS...
+...S..A...
+..A.A.MS.X
+XMASAMX.MM
+X.....XA.A
+S.S.S.S.SS
+.A.A.A.A.A
+..M.M.M.MM
+.X.X.XMASX
+";
+        let crosses = "\
+.M.S......
+..A..MSMS.
+.M.S.MAA..
+..A.ASMSM.
+.M.S.M....
+..........
+S.S.S.S.S.
+.A.A.A.A..
+M.M.M.M.M.
+..........
+";
+        assert_eq!(render_matches(&grid, RenderTarget::Words, false).unwrap(), words);
+        assert_eq!(render_matches(&grid, RenderTarget::Crosses, false).unwrap(), crosses);
+    }
+
+    #[test]
+    fn direction_breakdown_example() {
+        let grid = Grid::parse(EXAMPLE).unwrap();
+        let breakdown = direction_breakdown(&grid, "XMAS");
+        assert_eq!(breakdown.counts.len(), Direction::ALL.len());
+        assert_eq!(breakdown.counts.iter().map(|(_, n)| n).sum::<usize>(), 18);
+        assert!(!breakdown.palindrome);
+        assert_eq!(breakdown.same_cell_pairs, 0);
+    }
+
+    #[test]
+    fn direction_breakdown_palindrome() {
+        let grid = Grid::parse(EXAMPLE).unwrap();
+        let breakdown = direction_breakdown(&grid, "MAM");
+        let total: usize = breakdown.counts.iter().map(|(_, n)| n).sum();
+        assert!(breakdown.palindrome);
+        assert!(total > 0);
+        // Every occurrence is found once in each direction
+        assert_eq!(breakdown.same_cell_pairs * 2, total);
+    }
+
+    #[test]
+    fn stencil_without_letters_is_rejected() {
+        for text in ["", "\n\n", "...", ".\n.", "..\n.."] {
+            assert!(matches!(parse_stencil(text), Err(Error::Other(_))), "{:?}", text);
+        }
+        assert!(parse_stencil("..\n.A").is_ok());
+    }
+}
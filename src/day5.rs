@@ -1,18 +1,23 @@
 use crate::error::Error;
+use crate::parse::split_sections;
 use std::fs::File;
 use std::io::Read;
 use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
 
-fn parse_rules(rule_str: &str) -> HashMap<usize, HashSet<usize>> {
+/// Page -> the pages that must come after it.
+type Rules = HashMap<usize, HashSet<usize>>;
+
+fn parse_rules(rule_lines: &[&str]) -> Rules {
 
     let mut map = HashMap::new();
-    for rule in rule_str.split("\n") {
+    for rule in rule_lines {
         // Skip if | doesn't exist, or first/second parsing returns error
         let mut numbers = rule.split('|');
         let first = numbers.next();
         let second = numbers.next();
         if let (Some(x), Some(y)) = (first, second) {
-            if let (Ok(a), Ok(b)) = (x.parse::<usize>(), y.parse::<usize>()) {
+            if let (Ok(a), Ok(b)) = (x.trim().parse::<usize>(), y.trim().parse::<usize>()) {
                 map
                     .entry(a)
                     .and_modify(|s: &mut HashSet<usize>| {s.insert(b);})
@@ -23,13 +28,14 @@ fn parse_rules(rule_str: &str) -> HashMap<usize, HashSet<usize>> {
     map
 }
 
-fn parse_update_lists(update_str: &str) -> Vec<Vec<usize>> {
+fn parse_update_lists(update_lines: &[&str]) -> Vec<Vec<usize>> {
     let mut output_lists = Vec::new();
-    for update in update_str.split("\n") {
-        if !update.trim().is_empty() {
-            let update_list = update.trim().split(",").filter_map(|s| s.trim().parse::<usize>().ok()).collect::<Vec<_>>();
-            output_lists.push(update_list);
-        }
+    for update in update_lines {
+        let update_list = update
+            .split(',')
+            .filter_map(|s| s.trim().parse::<usize>().ok())
+            .collect::<Vec<_>>();
+        output_lists.push(update_list);
     }
     output_lists
 }
@@ -52,23 +58,67 @@ fn check_page_validity(rules: &HashMap<usize, HashSet<usize>>, page_nums: &[usiz
 }
 
 
-pub fn d5_part1_solution(input_path: &str) -> Result<usize, Error> {
-    let mut f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let mut buffer = String::new();
-    f.read_to_string(&mut buffer).map_err(|e| Error::IOError(e))?;
+/// A rule a|b broken by an update, where page a is at position after and page b is at position
+/// before, with before < after.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Violation {
+    pub rule: (usize, usize),
+    pub positions: (usize, usize),
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (a, b) = self.rule;
+        let (i, j) = self.positions;
+        write!(
+            f,
+            "page {} (position {}) appears after page {} (position {}) but rule {}|{}",
+            a, i, b, j, a, b
+        )
+    }
+}
+
+/// Like check_page_validity, but returns every rule the update breaks instead of stopping at
+/// the first one.
+fn page_violations(rules: &Rules, page_nums: &[usize]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (j, num) in page_nums.iter().enumerate() {
+        for (i, v) in page_nums.iter().enumerate().skip(j + 1) {
+            if let Some(numbers) = rules.get(v) {
+                if numbers.contains(num) {
+                    violations.push(Violation {
+                        rule: (*v, *num),
+                        positions: (i, j),
+                    });
+                }
+            }
+        }
+    }
+    violations
+}
 
-    let divide = "\n\n";
-    let divide_index = buffer
-        .find(divide)
-        .ok_or_else(|| Error::Other("Input file is not correct.".to_string()))?;
+/// Reads the input file and returns (rules, updates).
+fn load_input(input_path: &str) -> Result<(Rules, Vec<Vec<usize>>), Error> {
+    let mut f = File::open(input_path).map_err(Error::IOError)?;
+    let mut buffer = String::new();
+    f.read_to_string(&mut buffer).map_err(Error::IOError)?;
 
-    let (left, right) = buffer.split_at(divide_index);
+    match split_sections(&buffer).as_slice() {
+        [rules, updates] => Ok((parse_rules(rules), parse_update_lists(updates))),
+        sections => Err(Error::Other(format!(
+            "Input file is not correct. Expected the rules and the updates separated by a blank \
+             line, found {} sections.",
+            sections.len()
+        ))),
+    }
+}
 
-    let rules = parse_rules(left);
+pub fn d5_part1_solution(input_path: &str) -> Result<usize, Error> {
+    let (rules, updates) = load_input(input_path)?;
 
     let mut answer = 0usize;
 
-    for page in parse_update_lists(right) {
+    for page in updates {
         let valid = check_page_validity(&rules, &page);
         // The update sequence is valid. Find middle number
         if valid {
@@ -79,53 +129,137 @@ pub fn d5_part1_solution(input_path: &str) -> Result<usize, Error> {
     Ok(answer)
 }
 
-/// Returns whether or not the page_num vec is fixed. If false, then it must be correct.
-/// If true, it is fixed by the algo.
-fn recursive_fix_page_nums(rules: &HashMap<usize, HashSet<usize>>, page_nums: &mut Vec<usize>) -> bool {
-
-    let mut need_fix = false;
-    while !check_page_validity(rules, &page_nums) {
-        need_fix = true;
-        // Swap once for every loop
-        let mut swap: Option<(usize, usize)> = None;
-        'outer: for (i, num) in page_nums.iter().enumerate() {
-            // For every number to the right of num, they should not have an entry in the rules
-            // that contains num
-            // If they do, that means they should be to the left of num.
-            for (k, v) in page_nums[i+1..].iter().enumerate() {
-                if let Some(numbers) = rules.get(v) {
-                    if numbers.contains(num) {
-                        swap.replace((i, i + 1 + k));
-                        break 'outer
+/// Depth first search from page_nums[i] for a cycle in the rules restricted to page_nums.
+/// state[j] is 0 if page_nums[j] is not visited yet, 1 if it is on the current path and 2 if
+/// it is done. path holds the indices of the current path.
+fn dfs_cycle(
+    rules: &HashMap<usize, HashSet<usize>>,
+    page_nums: &[usize],
+    i: usize,
+    state: &mut [u8],
+    path: &mut Vec<usize>,
+) -> Option<Vec<usize>> {
+    state[i] = 1;
+    path.push(i);
+    if let Some(numbers) = rules.get(&page_nums[i]) {
+        for (j, b) in page_nums.iter().enumerate() {
+            if !numbers.contains(b) {
+                continue;
+            }
+            match state[j] {
+                // Back edge, the cycle is the part of the path starting at j
+                1 => {
+                    let mut cycle = path
+                        .iter()
+                        .skip_while(|&&k| k != j)
+                        .map(|&k| page_nums[k])
+                        .collect::<Vec<_>>();
+                    cycle.push(page_nums[j]);
+                    return Some(cycle);
+                }
+                0 => {
+                    if let Some(cycle) = dfs_cycle(rules, page_nums, j, state, path) {
+                        return Some(cycle);
                     }
                 }
+                _ => {}
             }
         }
-        if let Some((i, j)) = swap {
-            page_nums.swap(i, j);
+    }
+    path.pop();
+    state[i] = 2;
+    None
+}
+
+/// Finds a cycle in the rules restricted to the pages of an update. The cycle is returned as
+/// the pages along it, with the first page repeated at the end, e.g. [47, 53, 29, 47].
+fn find_cycle(rules: &HashMap<usize, HashSet<usize>>, page_nums: &[usize]) -> Option<Vec<usize>> {
+    let mut state = vec![0u8; page_nums.len()];
+    let mut path = Vec::new();
+    for i in 0..page_nums.len() {
+        if state[i] == 0 {
+            if let Some(cycle) = dfs_cycle(rules, page_nums, i, &mut state, &mut path) {
+                return Some(cycle);
+            }
         }
     }
-    need_fix
+    None
 }
-    
 
-pub fn d5_part2_solution(input_path: &str) -> Result<usize, Error> {
-    let mut f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let mut buffer = String::new();
-    f.read_to_string(&mut buffer).map_err(|e| Error::IOError(e))?;
+/// The error for an update whose rules contain a cycle, e.g. "47 -> 53 -> 29 -> 47".
+fn cycle_error(page_nums: &[usize], cycle: &[usize]) -> Error {
+    let cycle_str = cycle
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ");
+    Error::Other(format!(
+        "Rules for update {:?} contain a cycle: {}",
+        page_nums, cycle_str
+    ))
+}
 
-    let divide = "\n\n";
-    let divide_index = buffer
-        .find(divide)
-        .ok_or_else(|| Error::Other("Input file is not correct.".to_string()))?;
+/// Reorders page_nums so that every rule between two of its pages is respected, with a
+/// topological sort (Kahn's algorithm) of the rules restricted to the pages of the update.
+/// Among the pages that can go next, the one that comes first in page_nums is picked, so a valid
+/// update is left as is. Returns whether page_nums had to be reordered.
+///
+/// This is O(n^2) in the length of the update, with one hash lookup per pair of pages.
+fn topo_sort_page_nums(
+    rules: &HashMap<usize, HashSet<usize>>,
+    page_nums: &mut Vec<usize>,
+) -> Result<bool, Error> {
+    let n = page_nums.len();
+    // before[j] = the number of pages in the update that must come before page_nums[j]
+    let mut before = vec![0usize; n];
+    for a in page_nums.iter() {
+        if let Some(numbers) = rules.get(a) {
+            for (j, b) in page_nums.iter().enumerate() {
+                before[j] += numbers.contains(b) as usize;
+            }
+        }
+    }
+
+    let mut placed = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for _ in 0..n {
+        let Some(i) = (0..n).find(|&i| !placed[i] && before[i] == 0) else {
+            // Every page left has another page that must come before it, so the pages left
+            // contain a cycle.
+            let left = (0..n)
+                .filter(|&i| !placed[i])
+                .map(|i| page_nums[i])
+                .collect::<Vec<_>>();
+            return match find_cycle(rules, &left) {
+                Some(cycle) => Err(cycle_error(page_nums, &cycle)),
+                None => Err(Error::Other(format!(
+                    "Rules for update {:?} contain a cycle.",
+                    page_nums
+                ))),
+            };
+        };
+        placed[i] = true;
+        order.push(page_nums[i]);
+        if let Some(numbers) = rules.get(&page_nums[i]) {
+            for (j, b) in page_nums.iter().enumerate() {
+                if !placed[j] && numbers.contains(b) {
+                    before[j] -= 1;
+                }
+            }
+        }
+    }
 
-    let (left, right) = buffer.split_at(divide_index);
+    let need_fix = order != *page_nums;
+    *page_nums = order;
+    Ok(need_fix)
+}
 
-    let rules = parse_rules(left);
+pub fn d5_part2_solution(input_path: &str) -> Result<usize, Error> {
+    let (rules, updates) = load_input(input_path)?;
 
     let mut answer = 0usize;
-    for mut page in parse_update_lists(right) {
-        let fixed = recursive_fix_page_nums(&rules, &mut page);
+    for mut page in updates {
+        let fixed = topo_sort_page_nums(&rules, &mut page)?;
         // The update sequence is valid. Find middle number
         if fixed {
             let mid = page.len() / 2;
@@ -135,4 +269,493 @@ pub fn d5_part2_solution(input_path: &str) -> Result<usize, Error> {
     }
     Ok(answer)
 }
-    
\ No newline at end of file
+    
+
+/// Returns the rules as a Graphviz DOT digraph, with an edge a -> b for every rule a|b.
+/// If update_index is given, only the pages of that update and the rules between them are
+/// kept, and the rules the update violates are drawn in red.
+fn rules_dot(
+    rules: &Rules,
+    updates: &[Vec<usize>],
+    update_index: Option<usize>,
+) -> Result<String, Error> {
+    let mut edges = rules
+        .iter()
+        .flat_map(|(a, numbers)| numbers.iter().map(move |b| (*a, *b)))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+
+    let mut dot = String::from("digraph rules {\n");
+    match update_index {
+        None => {
+            for (a, b) in edges {
+                dot.push_str(&format!("    {} -> {};\n", a, b));
+            }
+        }
+        Some(k) => {
+            let page_nums = updates.get(k).ok_or_else(|| {
+                Error::Other(format!(
+                    "Update {} doesn't exist. There are {} updates.",
+                    k,
+                    updates.len()
+                ))
+            })?;
+            let pages: HashSet<usize> = page_nums.iter().copied().collect();
+            let violated: HashSet<(usize, usize)> = page_violations(rules, page_nums)
+                .into_iter()
+                .map(|v| v.rule)
+                .collect();
+            for p in page_nums {
+                dot.push_str(&format!("    {};\n", p));
+            }
+            for (a, b) in edges {
+                if !pages.contains(&a) || !pages.contains(&b) {
+                    continue;
+                }
+                if violated.contains(&(a, b)) {
+                    dot.push_str(&format!("    {} -> {} [color=red];\n", a, b));
+                } else {
+                    dot.push_str(&format!("    {} -> {};\n", a, b));
+                }
+            }
+        }
+    }
+    dot.push_str("}\n");
+    Ok(dot)
+}
+
+/// Like rules_dot, for the rules and updates in the input.
+pub fn d5_rules_dot(input_path: &str, update_index: Option<usize>) -> Result<String, Error> {
+    let (rules, updates) = load_input(input_path)?;
+    rules_dot(&rules, &updates, update_index)
+}
+
+/// An update that breaks at least one rule.
+pub struct InvalidUpdate {
+    pub index: usize,
+    pub page_nums: Vec<usize>,
+    pub violations: Vec<Violation>,
+}
+
+/// Returns every update that breaks at least one rule, with the rules it breaks.
+pub fn d5_violations(input_path: &str) -> Result<Vec<InvalidUpdate>, Error> {
+    let (rules, updates) = load_input(input_path)?;
+    Ok(updates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, page_nums)| {
+            let violations = page_violations(&rules, &page_nums);
+            (!violations.is_empty()).then_some(InvalidUpdate {
+                index,
+                page_nums,
+                violations,
+            })
+        })
+        .collect())
+}
+
+/// How far an invalid update is from a valid order.
+pub struct ReorderAnalysis {
+    pub index: usize,
+    pub page_nums: Vec<usize>,
+    /// The minimum number of swaps of neighbouring pages to reach a valid order. None if the
+    /// update has too many pages or valid orders to find it.
+    pub min_adjacent_swaps: Option<usize>,
+    /// The minimum number of pages to take out and put back elsewhere to reach a valid order.
+    pub min_moved_pages: usize,
+    /// Whether there is exactly one valid order for the pages of the update. None when
+    /// min_adjacent_swaps is None.
+    pub unique_order: Option<bool>,
+}
+
+/// must_precede[i][j] is true if page_nums[i] must come before page_nums[j], either by a rule or
+/// by a chain of rules between pages of the update.
+fn precedence_closure(rules: &Rules, page_nums: &[usize]) -> Vec<Vec<bool>> {
+    let n = page_nums.len();
+    let mut must_precede = vec![vec![false; n]; n];
+    for (i, a) in page_nums.iter().enumerate() {
+        if let Some(numbers) = rules.get(a) {
+            for (j, b) in page_nums.iter().enumerate() {
+                must_precede[i][j] = numbers.contains(b);
+            }
+        }
+    }
+    // Floyd-Warshall. Updates are short, so O(n^3) is fine.
+    for k in 0..n {
+        for i in 0..n {
+            if must_precede[i][k] {
+                for j in 0..n {
+                    must_precede[i][j] |= must_precede[k][j];
+                }
+            }
+        }
+    }
+    must_precede
+}
+
+/// The most closed sets of one size that min_adjacent_swaps keeps before giving up.
+const MAX_CLOSED_SETS: usize = 1 << 16;
+
+/// Returns (minimum number of adjacent swaps, whether the valid order is unique), or None if
+/// there are more than MAX_CLOSED_SETS closed sets of some size.
+///
+/// A valid order is built by placing one page at a time, and the pages placed so far are always
+/// a set closed under "must come before". Placing page x after the set placed creates one
+/// inversion with each page not placed yet that is to the left of x in the update, so the
+/// swaps needed are the sum of those, minimized by a DP over the closed sets. The number of
+/// closed sets is exponential in the worst case, but it is n + 1 when the rules give a total
+/// order, which is the case for the AOC input. The order is unique if and only if there is a
+/// single closed set of each size.
+fn min_adjacent_swaps(must_precede: &[Vec<bool>]) -> Option<(usize, bool)> {
+    let n = must_precede.len();
+    // preceding[x] = mask of the pages that must come before page x
+    let preceding = (0..n)
+        .map(|x| (0..n).filter(|&i| must_precede[i][x]).fold(0u64, |m, i| m | (1 << i)))
+        .collect::<Vec<_>>();
+
+    let mut unique = true;
+    let mut layer: HashMap<u64, usize> = HashMap::from([(0, 0)]);
+    for _ in 0..n {
+        let mut next: HashMap<u64, usize> = HashMap::new();
+        for (&placed, &cost) in &layer {
+            for (x, before_x) in preceding.iter().enumerate() {
+                if placed & (1 << x) != 0 || before_x & !placed != 0 {
+                    continue;
+                }
+                let left_not_placed = (!placed & ((1u64 << x) - 1)).count_ones() as usize;
+                match next.entry(placed | (1 << x)) {
+                    Entry::Occupied(mut e) => {
+                        let c = e.get_mut();
+                        *c = (*c).min(cost + left_not_placed);
+                    }
+                    Entry::Vacant(e) => {
+                        e.insert(cost + left_not_placed);
+                    }
+                }
+            }
+        }
+        if next.len() > MAX_CLOSED_SETS {
+            return None;
+        }
+        unique &= next.len() == 1;
+        layer = next;
+    }
+    // Only the full set is left, or nothing if the rules have a cycle.
+    let swaps = layer.into_values().next()?;
+    Some((swaps, unique))
+}
+
+/// Returns the minimum number of pages to move.
+///
+/// Say i < j conflict if page j must come before page i. The pages that stay must have no
+/// conflicts between them, and any such set can stay. Conflicts form a partial order, so the
+/// largest such set is a maximum antichain, which by Dilworth's theorem has size n minus the
+/// maximum matching from i to j over the conflicts. The pages moved are the matching size.
+fn min_moved_pages(must_precede: &[Vec<bool>]) -> usize {
+    let n = must_precede.len();
+    // matched_to[j] = the i matched with j
+    let mut matched_to: Vec<Option<usize>> = vec![None; n];
+    let mut matching = 0usize;
+    for i in 0..n {
+        let mut seen = vec![false; n];
+        matching += augment(must_precede, i, &mut seen, &mut matched_to) as usize;
+    }
+    matching
+}
+
+/// Kuhn's algorithm. Tries to match i with a page j > i that conflicts with it.
+fn augment(
+    must_precede: &[Vec<bool>],
+    i: usize,
+    seen: &mut [bool],
+    matched_to: &mut [Option<usize>],
+) -> bool {
+    for j in i + 1..must_precede.len() {
+        if !must_precede[j][i] || seen[j] {
+            continue;
+        }
+        seen[j] = true;
+        let free = match matched_to[j] {
+            None => true,
+            Some(k) => augment(must_precede, k, seen, matched_to),
+        };
+        if free {
+            matched_to[j] = Some(i);
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns the reordering analysis for every update that breaks at least one rule. The minimum
+/// number of swaps is left out for the updates where it can't be found, instead of failing.
+fn reorder_analysis(
+    rules: &Rules,
+    updates: Vec<Vec<usize>>,
+) -> Result<Vec<ReorderAnalysis>, Error> {
+    let mut output = Vec::new();
+    for (index, page_nums) in updates.into_iter().enumerate() {
+        if check_page_validity(rules, &page_nums) {
+            continue;
+        }
+        if let Some(cycle) = find_cycle(rules, &page_nums) {
+            return Err(cycle_error(&page_nums, &cycle));
+        }
+        let must_precede = precedence_closure(rules, &page_nums);
+        // The DP keeps the set of placed pages in a u64.
+        let swaps = if page_nums.len() > 64 {
+            None
+        } else {
+            min_adjacent_swaps(&must_precede)
+        };
+        output.push(ReorderAnalysis {
+            index,
+            page_nums,
+            min_adjacent_swaps: swaps.map(|(swaps, _)| swaps),
+            min_moved_pages: min_moved_pages(&must_precede),
+            unique_order: swaps.map(|(_, unique)| unique),
+        });
+    }
+    Ok(output)
+}
+
+/// Returns the reordering analysis for every update in the input that breaks at least one rule.
+pub fn d5_reorder_analysis(input_path: &str) -> Result<Vec<ReorderAnalysis>, Error> {
+    let (rules, updates) = load_input(input_path)?;
+    reorder_analysis(&rules, updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+
+    const EXAMPLE: &str = "47|53
+97|13
+97|61
+97|47
+75|29
+61|13
+75|53
+29|13
+97|29
+53|29
+61|53
+97|53
+61|29
+47|13
+75|47
+97|75
+47|61
+75|61
+47|29
+75|13
+53|13
+
+75,47,61,53,29
+97,61,53,29,13
+75,29,13
+75,97,47,61,53
+61,13,29
+97,13,75,29,47
+";
+
+    fn parse(text: &str) -> (Rules, Vec<Vec<usize>>) {
+        let sections = split_sections(text);
+        (parse_rules(&sections[0]), parse_update_lists(&sections[1]))
+    }
+
+    /// The swap based fixer this module used before the topological sort, kept to check
+    /// against. It only terminates if the rules between the pages of the update have no cycle.
+    fn recursive_fix_page_nums(rules: &Rules, page_nums: &mut [usize]) -> bool {
+        let mut need_fix = false;
+        while !check_page_validity(rules, page_nums) {
+            need_fix = true;
+            let mut swap = None;
+            'outer: for (i, num) in page_nums.iter().enumerate() {
+                for (k, v) in page_nums[i + 1..].iter().enumerate() {
+                    if rules.get(v).is_some_and(|numbers| numbers.contains(num)) {
+                        swap = Some((i, i + 1 + k));
+                        break 'outer;
+                    }
+                }
+            }
+            if let Some((i, j)) = swap {
+                page_nums.swap(i, j);
+            }
+        }
+        need_fix
+    }
+
+    /// Checks that both fixers agree on page_nums, and returns the fixed update.
+    fn check_fixers_agree(rules: &Rules, page_nums: &[usize]) -> Vec<usize> {
+        let mut sorted = page_nums.to_vec();
+        let mut swapped = page_nums.to_vec();
+        let fixed = topo_sort_page_nums(rules, &mut sorted).unwrap();
+        assert_eq!(fixed, recursive_fix_page_nums(rules, &mut swapped));
+        assert_eq!(sorted, swapped, "{:?}", page_nums);
+        assert!(check_page_validity(rules, &sorted));
+        sorted
+    }
+
+    #[test]
+    fn topo_sort_example() {
+        let (rules, updates) = parse(EXAMPLE);
+        let mut answer = 0;
+        for page_nums in &updates {
+            let fixed = check_fixers_agree(&rules, page_nums);
+            if check_page_validity(&rules, page_nums) {
+                // Valid updates come back unchanged
+                assert_eq!(&fixed, page_nums);
+            } else {
+                answer += fixed[fixed.len() / 2];
+            }
+        }
+        assert_eq!(answer, 123);
+    }
+
+    #[test]
+    fn topo_sort_matches_swaps_on_real_rules() {
+        let (rules, updates) = load_input("inputs/d5_input.txt").unwrap();
+        let mut rng = Rng::new(34);
+        for page_nums in &updates {
+            if check_page_validity(&rules, page_nums) {
+                let mut sorted = page_nums.clone();
+                assert!(!topo_sort_page_nums(&rules, &mut sorted).unwrap());
+                assert_eq!(&sorted, page_nums);
+            }
+            // The pages of an update have no cycle between them, so any subset in any order
+            // can be fixed by both.
+            for _ in 0..5 {
+                let mut pages = page_nums.clone();
+                rng.shuffle(&mut pages);
+                pages.truncate(rng.below(pages.len() + 1));
+                check_fixers_agree(&rules, &pages);
+            }
+        }
+    }
+
+    #[test]
+    fn cycle_is_reported() {
+        let (rules, _) = parse("47|53\n53|29\n29|47\n\n1");
+        let mut page_nums = vec![29, 47, 53];
+        assert_eq!(find_cycle(&rules, &page_nums), Some(vec![29, 47, 53, 29]));
+        match topo_sort_page_nums(&rules, &mut page_nums) {
+            Err(Error::Other(msg)) => assert!(msg.ends_with("29 -> 47 -> 53 -> 29"), "{}", msg),
+            _ => panic!("expected a cycle error"),
+        }
+        assert_eq!(find_cycle(&rules, &[47, 53]), None);
+    }
+
+    /// Returns (swaps, moved pages, unique order) for page_nums.
+    fn reorder(rules: &Rules, page_nums: &[usize]) -> (usize, usize, bool) {
+        let must_precede = precedence_closure(rules, page_nums);
+        let (swaps, unique) = min_adjacent_swaps(&must_precede).unwrap();
+        (swaps, min_moved_pages(&must_precede), unique)
+    }
+
+    #[test]
+    fn reorder_example() {
+        let (rules, _) = parse(EXAMPLE);
+        // 97,75,47,61,53 is one swap away
+        assert_eq!(reorder(&rules, &[75, 97, 47, 61, 53]), (1, 1, true));
+        assert_eq!(reorder(&rules, &[61, 13, 29]), (1, 1, true));
+        // The valid order is 97,75,47,29,13, so the positions are 0,4,1,3,2: 4 inversions, and
+        // 0,1,3 can stay
+        assert_eq!(reorder(&rules, &[97, 13, 75, 29, 47]), (4, 2, true));
+        assert_eq!(reorder(&rules, &[75, 47, 61, 53, 29]), (0, 0, true));
+    }
+
+    #[test]
+    fn reorder_small_updates() {
+        let (rules, _) = parse("1|2\n1|3\n2|3\n\n1");
+        assert_eq!(reorder(&rules, &[3, 2, 1]), (3, 2, true));
+        assert_eq!(reorder(&rules, &[2, 1, 3]), (1, 1, true));
+        assert_eq!(reorder(&rules, &[2, 3, 1]), (2, 1, true));
+
+        // 3 is free, so 1,2,3 / 1,3,2 / 3,1,2 are all valid
+        let (rules, _) = parse("1|2\n\n1");
+        assert_eq!(reorder(&rules, &[2, 1, 3]), (1, 1, false));
+        assert_eq!(reorder(&rules, &[2, 3, 1]), (2, 1, false));
+
+        // 4 must move past 1 and 2, but moving 4 alone is enough
+        let (rules, _) = parse("1|4\n2|4\n\n1");
+        assert_eq!(reorder(&rules, &[4, 1, 2, 3]), (2, 1, false));
+
+        // 3 must come before 1 and 2 before 4: moving 3 and 2 alone (or 1 and 4) is enough
+        let (rules, _) = parse("3|1\n2|4\n\n1");
+        assert_eq!(reorder(&rules, &[1, 4, 3, 2]), (3, 2, false));
+    }
+
+    #[test]
+    fn reorder_gives_up_on_too_many_orders() {
+        // Only the first and last pages are ordered, so nearly every subset is a closed set
+        let (rules, _) = parse("40|1\n\n1");
+        let page_nums = (1..=40).collect::<Vec<_>>();
+        let must_precede = precedence_closure(&rules, &page_nums);
+        assert_eq!(min_adjacent_swaps(&must_precede), None);
+    }
+
+    #[test]
+    fn rules_dot_example() {
+        let (rules, updates) = parse(EXAMPLE);
+        let dot = rules_dot(&rules, &updates, None).unwrap();
+        assert!(dot.starts_with("digraph rules {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(dot.matches(" -> ").count(), 21);
+
+        // 75,97,47,61,53 breaks 97|75
+        let dot = rules_dot(&rules, &updates, Some(3)).unwrap();
+        assert!(dot.contains("    97 -> 75 [color=red];\n"), "{}", dot);
+        assert_eq!(dot.matches("[color=red]").count(), 1);
+        let pages = ["75", "97", "47", "61", "53"];
+        for line in dot.lines().filter(|l| l.contains(" -> ")) {
+            let edge = line.trim().trim_end_matches(';').trim_end_matches(" [color=red]");
+            let (a, b) = edge.split_once(" -> ").unwrap();
+            assert!(pages.contains(&a) && pages.contains(&b), "{}", line);
+        }
+        // The 5 pages have a rule between every pair
+        assert_eq!(dot.matches(" -> ").count(), 10);
+
+        assert!(matches!(rules_dot(&rules, &updates, Some(6)), Err(Error::Other(_))));
+    }
+
+    #[test]
+    fn page_violations_example() {
+        let (rules, _) = parse(EXAMPLE);
+        assert_eq!(
+            page_violations(&rules, &[75, 97, 47, 61, 53]),
+            vec![Violation { rule: (97, 75), positions: (1, 0) }]
+        );
+        // 13 comes before 75, 29 and 47, and 29 before 47
+        let violations = page_violations(&rules, &[97, 13, 75, 29, 47]);
+        assert_eq!(violations.len(), 4);
+        assert!(violations.iter().all(|v| v.positions.1 < v.positions.0));
+        assert!(page_violations(&rules, &[75, 47, 61, 53, 29]).is_empty());
+        assert_eq!(
+            violations[0].to_string(),
+            "page 75 (position 2) appears after page 13 (position 1) but rule 75|13"
+        );
+    }
+
+    #[test]
+    fn reorder_analysis_keeps_going() {
+        let (rules, _) = parse("40|1\n2|1\n\n1");
+        let updates = vec![
+            (1..=40).collect::<Vec<_>>(),
+            vec![1, 2],
+            (1..=70).collect::<Vec<_>>(),
+            vec![2, 1],
+        ];
+        let analysis = reorder_analysis(&rules, updates).unwrap();
+        let rows = analysis
+            .iter()
+            .map(|a| (a.index, a.min_adjacent_swaps, a.min_moved_pages, a.unique_order))
+            .collect::<Vec<_>>();
+        // Too many valid orders, then too many pages, but the moved pages are still found
+        assert_eq!(
+            rows,
+            vec![(0, None, 1, None), (1, Some(1), 1, Some(true)), (2, None, 1, None)]
+        );
+    }
+}
@@ -1,9 +1,46 @@
 use crate::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::collections::HashSet;
+use std::io::Read;
+use std::collections::{HashMap, HashSet};
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+/// The map of the lab, with the size of the input.
+struct Map {
+    rows: usize,
+    cols: usize,
+    /// True if walkable, row by row.
+    walkable: Vec<bool>,
+}
+
+impl Map {
+    /// The number of positions in the map.
+    fn len(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    /// The index of (x, y) when the positions are stored row by row.
+    fn index(&self, x: usize, y: usize) -> usize {
+        x * self.cols + y
+    }
+
+    fn is_walkable(&self, x: usize, y: usize) -> bool {
+        self.walkable[self.index(x, y)]
+    }
+
+    /// The next position from (x, y) in direction, or None if it is out of the map.
+    fn step(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        match direction {
+            Direction::UP => x.checked_sub(1).map(|x| (x, y)),
+            Direction::LEFT => y.checked_sub(1).map(|y| (x, y)),
+            Direction::RIGHT => (y + 1 < self.cols).then_some((x, y + 1)),
+            Direction::DOWN => (x + 1 < self.rows).then_some((x + 1, y)),
+        }
+    }
+}
+
+/// Position and direction of the guard.
+type State = (usize, usize, Direction);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 enum Direction {
     UP,
     LEFT,
@@ -12,8 +49,15 @@ enum Direction {
 }
 
 impl Direction {
-    fn new() -> Self {
-        Self::UP
+    /// The direction of a guard drawn as c, or None if c is not a guard.
+    fn from_glyph(c: char) -> Option<Self> {
+        match c {
+            '^' => Some(Self::UP),
+            '>' => Some(Self::RIGHT),
+            'v' => Some(Self::DOWN),
+            '<' => Some(Self::LEFT),
+            _ => None,
+        }
     }
 
     fn turn(&self) -> Self {
@@ -24,6 +68,89 @@ impl Direction {
             Self::DOWN => Self::LEFT,
         }
     }
+
+    /// How the guard facing this direction is drawn.
+    fn glyph(&self) -> char {
+        match self {
+            Self::UP => '^',
+            Self::RIGHT => '>',
+            Self::DOWN => 'v',
+            Self::LEFT => '<',
+        }
+    }
+
+    /// Index of the direction, in the order the guard turns.
+    fn index(&self) -> usize {
+        match self {
+            Self::UP => 0,
+            Self::RIGHT => 1,
+            Self::DOWN => 2,
+            Self::LEFT => 3,
+        }
+    }
+}
+
+/// For every position and direction, where the guard stops when walking straight from there:
+/// the last position before the next obstacle, or None if the guard walks out of the map.
+/// This lets the guard jump from one turn to the next instead of walking one step at a time.
+struct JumpTable {
+    cols: usize,
+    jumps: Vec<[Option<(usize, usize)>; 4]>,
+}
+
+impl JumpTable {
+    fn new(map: &Map) -> Self {
+        let n = map.len();
+        let mut jumps = vec![[None; 4]; n];
+        for direction in [Direction::UP, Direction::RIGHT, Direction::DOWN, Direction::LEFT] {
+            let d = direction.index();
+            // Visit the positions so that the next position in direction is always done first.
+            let order = (0..n).map(|k| match direction {
+                Direction::UP | Direction::LEFT => k,
+                Direction::DOWN | Direction::RIGHT => n - 1 - k,
+            });
+            for k in order {
+                let (x, y) = (k / map.cols, k % map.cols);
+                jumps[k][d] = match map.step(x, y, direction) {
+                    None => None,
+                    Some((u, v)) if !map.is_walkable(u, v) => Some((x, y)),
+                    Some((u, v)) => jumps[map.index(u, v)][d],
+                };
+            }
+        }
+        JumpTable { cols: map.cols, jumps }
+    }
+
+    /// Where the guard stops when walking from (x, y) in direction, with an extra obstacle
+    /// that is not in the table.
+    fn jump(
+        &self,
+        x: usize,
+        y: usize,
+        direction: Direction,
+        obstacle: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let target = self.jumps[x * self.cols + y][direction.index()];
+        let (ox, oy) = obstacle;
+        // Whether the obstacle is ahead of the guard, and not behind the target.
+        let blocks = match direction {
+            Direction::UP => oy == y && ox < x && target.map(|(tx, _)| ox >= tx).unwrap_or(true),
+            Direction::DOWN => oy == y && ox > x && target.map(|(tx, _)| ox <= tx).unwrap_or(true),
+            Direction::LEFT => ox == x && oy < y && target.map(|(_, ty)| oy >= ty).unwrap_or(true),
+            Direction::RIGHT => ox == x && oy > y && target.map(|(_, ty)| oy <= ty).unwrap_or(true),
+        };
+        if blocks {
+            // Stop right before the obstacle
+            match direction {
+                Direction::UP => Some((ox + 1, oy)),
+                Direction::DOWN => Some((ox - 1, oy)),
+                Direction::LEFT => Some((ox, oy + 1)),
+                Direction::RIGHT => Some((ox, oy - 1)),
+            }
+        } else {
+            target
+        }
+    }
 }
 
 
@@ -38,13 +165,13 @@ pub struct Guard {
 
 impl Guard {
 
-    fn new(x: usize, y:usize) -> Self {
+    fn new(x: usize, y:usize, direction: Direction) -> Self {
         let mut hs = HashSet::new();
-        hs.insert((x, y, Direction::new()));
+        hs.insert((x, y, direction));
         Guard {
             x: x,
             y: y,
-            direction: Direction::new(),
+            direction,
             trail: hs,
             oob: false,
             in_loop: false,
@@ -59,52 +186,18 @@ impl Guard {
         self.direction = self.direction.turn();
     }
 
-    fn check_and_move(&mut self, map: &[[bool; 130];130]) {
+    fn check_and_move(&mut self, map: &Map) {
 
         if self.oob {
             return
         }
 
-        // If either x or y is >= 130, it means we move out of bounds.
-        let next_pos: (usize, usize) = match self.direction {
-            Direction::UP => {
-                if self.x >= 1 {
-                    (self.x - 1, self.y)
-                } else {
-                    (9999, 9999) // oob
-                }
-            },
-            Direction::LEFT => {
-                if self.y >= 1 {
-                    (self.x, self.y - 1)
-                } else {
-                    (9999, 9999)
-                }
-            },
-            Direction::RIGHT => {
-                if self.y + 1 >= 130 {
-                    (9999, 9999)
-                } else {
-                    (self.x, self.y + 1)
-                }
-
-            },
-            Direction::DOWN => {
-                if self.x + 1 >= 130 {
-                    (9999, 9999)
-                } else {
-                    (self.x + 1, self.y)
-                }
-            },
-        };
-
-        let (x, y) = next_pos;
-        if x >= 130 || y >= 130 {
+        let Some((x, y)) = map.step(self.x, self.y, self.direction) else {
             self.oob = true;
             return
-        }
+        };
 
-        if map[x][y] { // is obstacle
+        if map.is_walkable(x, y) {
             // reachable.
 
             // Stuck in a loop is equivalent to being in the same position
@@ -123,83 +216,781 @@ impl Guard {
     }
 }
 
-/// Returns the (map, starting position of guard) 
-fn load_map(input_path: &str) -> Result<([[bool;130];130], (usize, usize)), Error> {
+/// Returns the (map, starting state of guard). The map has the size of the input, which must be
+/// rectangular. The guard can face any direction, and there must be exactly one.
+fn parse_map(text: &str) -> Result<(Map, State), Error> {
+    let lines = text
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    let rows = lines.len();
+    let cols = lines.first().map(|line| line.chars().count()).unwrap_or(0);
 
-    let f = File::open(input_path).map_err(|e| Error::IOError(e))?;
-    let reader = BufReader::new(f);
-    let mut x = 0usize;
-    let mut y = 0usize;
-    let mut walkable = [[true; 130]; 130];
-    for (i, l) in reader.lines().enumerate() {
-        if let Ok(line) = l {
-            for (j, c) in line.chars().enumerate() {
-                walkable[i][j] = c != '#';
-                if c == '^' {
-                    x = i;
-                    y = j;
-                }
+    let mut guards = Vec::new();
+    let mut walkable = Vec::with_capacity(rows * cols);
+    for (i, line) in lines.iter().enumerate() {
+        let len = line.chars().count();
+        if len != cols {
+            return Err(Error::Other(format!(
+                "Map is not rectangular. Row {} has length {}, expected {}.",
+                i, len, cols
+            )));
+        }
+        for (j, c) in line.chars().enumerate() {
+            walkable.push(c != '#');
+            if let Some(direction) = Direction::from_glyph(c) {
+                guards.push((i, j, direction));
             }
-        } else {
-            return Err(Error::Other("Input file is not correct.".into())) 
         }
+    }
+    let map = Map { rows, cols, walkable };
+    match guards[..] {
+        [start] => Ok((map, start)),
+        [] => Err(Error::Other("No guard in the map.".into())),
+        _ => Err(Error::Other(format!("Found {} guards in the map, expected 1.", guards.len()))),
+    }
+}
 
+fn load_map(input_path: &str) -> Result<(Map, State), Error> {
+    let mut f = File::open(input_path).map_err(Error::IOError)?;
+    let mut buffer = String::new();
+    f.read_to_string(&mut buffer).map_err(Error::IOError)?;
+    parse_map(&buffer)
+}
+
+/// The number of distinct positions the guard visits before leaving the map, or before it
+/// starts repeating itself if it loops.
+fn distinct_places(map: &Map, start: State) -> usize {
+    let (x, y, direction) = start;
+    let mut guard = Guard::new(x, y, direction);
+    while !guard.oob && !guard.in_loop {
+        guard.check_and_move(map);
     }
-    Ok((walkable, (x, y)))
+    let only_positions_hashset: HashSet<(usize, usize)> =
+        HashSet::from_iter(guard.trail.iter().map(|(x, y, _)| (*x, *y)));
+    only_positions_hashset.len()
 }
 
 pub fn d6_part1_solution(input_path: &str) -> Result<usize, Error> {
+    let (map, starting_pos) = load_map(input_path)?;
+    Ok(distinct_places(&map, starting_pos))
+}
+
+/// A set of guard states, stored densely by (position, direction). Every slot holds the
+/// generation it was last inserted in, and only counts as set if that is the current generation.
+/// Clearing bumps the generation, so it is O(1) and the set can be reused without allocating.
+struct StateSet {
+    cols: usize,
+    generation: u32,
+    slots: Vec<u32>,
+}
+
+impl StateSet {
+    fn new(map: &Map) -> Self {
+        StateSet {
+            cols: map.cols,
+            generation: 1,
+            slots: vec![0; map.len() * 4],
+        }
+    }
+
+    fn clear(&mut self) {
+        if self.generation == u32::MAX {
+            // Only after 4 billion clears. Old generations could look current again, so reset.
+            self.slots.fill(0);
+            self.generation = 0;
+        }
+        self.generation += 1;
+    }
+
+    /// Returns false if the state was already in the set.
+    fn insert(&mut self, state: State) -> bool {
+        let (x, y, direction) = state;
+        let slot = &mut self.slots[(x * self.cols + y) * 4 + direction.index()];
+        let is_new = *slot != self.generation;
+        *slot = self.generation;
+        is_new
+    }
+}
+
+/// Checks if the guard starting at (x, y) facing direction loops once obstacle is added to the
+/// map. The guard jumps from turn to turn, so only the states where it turns are remembered,
+/// in turns. turns is cleared first, so it can be reused between calls.
+fn check_loop_with_obstacle(
+    jumps: &JumpTable,
+    start: State,
+    obstacle: (usize, usize),
+    turns: &mut StateSet,
+) -> bool {
+    let (mut x, mut y, mut direction) = start;
+    turns.clear();
+    while let Some((u, v)) = jumps.jump(x, y, direction, obstacle) {
+        // Stuck in a loop is equivalent to turning at the same position in the same direction
+        if !turns.insert((u, v, direction)) {
+            return true;
+        }
+        (x, y) = (u, v);
+        direction = direction.turn();
+    }
+    false
+}
+
+/// Walks the guard along its original path and returns every position it visits, except the
+/// start, together with the state of the guard right before it first steps there.
+///
+/// An obstacle can only change the path if it is on the path. And up to the first time the guard
+/// would step on it, the path is the same as without the obstacle, so the guard can start
+/// from there instead of from the start. If the original path is itself a loop, the walk stops
+/// once it comes back to a state it has seen, and every other walkable position is a candidate
+/// too, with the start as its state, since an obstacle there leaves the loop as it is.
+fn path_candidates(
+    map: &Map,
+    start: State,
+) -> Vec<((usize, usize), State)> {
+    let (mut x, mut y, mut direction) = start;
+    let mut visited = vec![false; map.len()];
+    visited[map.index(x, y)] = true;
+    let mut seen_states = vec![[false; 4]; map.len()];
+
+    let mut candidates = Vec::new();
+    while let Some((u, v)) = map.step(x, y, direction) {
+        let state = &mut seen_states[map.index(x, y)][direction.index()];
+        if *state {
+            let off_path = visited.iter().zip(&map.walkable).enumerate();
+            for (k, _) in off_path.filter(|(_, (&seen, &walkable))| !seen && walkable) {
+                candidates.push(((k / map.cols, k % map.cols), start));
+            }
+            break;
+        }
+        *state = true;
+        if !map.is_walkable(u, v) {
+            direction = direction.turn();
+            continue;
+        }
+        if !visited[map.index(u, v)] {
+            visited[map.index(u, v)] = true;
+            candidates.push(((u, v), (x, y, direction)));
+        }
+        (x, y) = (u, v);
+    }
+    candidates
+}
+
+/// Returns the positions where a new obstacle makes the guard loop, in the order the guard first
+/// visits them. The candidates are split into jobs chunks that are checked in parallel, and the
+/// chunks are put back together in order, so the result doesn't depend on jobs.
+fn find_loop_obstacles(
+    map: &Map,
+    start: State,
+    jobs: usize,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let jumps = JumpTable::new(map);
+    let candidates = path_candidates(map, start);
+    let chunk_size = candidates.len().div_ceil(jobs.max(1)).max(1);
+
+    std::thread::scope(|s| {
+        let handles = candidates
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let jumps = &jumps;
+                s.spawn(move || {
+                    // One set for all the simulations of this thread
+                    let mut turns = StateSet::new(map);
+                    chunk
+                        .iter()
+                        .filter(|(obstacle, state)| {
+                            check_loop_with_obstacle(jumps, *state, *obstacle, &mut turns)
+                        })
+                        .map(|(obstacle, _)| *obstacle)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut obstacles = Vec::new();
+        for handle in handles {
+            let found = handle
+                .join()
+                .map_err(|_| Error::Other("A day 6 worker thread panicked.".into()))?;
+            obstacles.extend(found);
+        }
+        Ok(obstacles)
+    })
+}
+
+/// jobs is the number of threads to use.
+pub fn d6_part2_solution(input_path: &str, jobs: usize) -> Result<usize, Error> {
     // True if walkable
     let (map, starting_pos) = load_map(input_path)?;
-    let (x, y) = starting_pos;
-    let mut guard = Guard::new(x, y);
-    
-    let distinct_places = loop {
-        guard.check_and_move(&map);
-        if guard.oob {
-            let only_positions_hashset:HashSet<(usize, usize)> = 
-                HashSet::from_iter(
-                    guard.trail.iter().map(|(x, y, _)| (*x, *y))
-                );
+    Ok(find_loop_obstacles(&map, starting_pos, jobs)?.len())
+}
+
+/// The map with the path of the guard drawn on it, like in the puzzle text: '|' and '-' where the
+/// guard walks up/down and left/right, and '+' where it does both or turns.
+struct PathCanvas {
+    cells: Vec<Vec<char>>,
+    start: (usize, usize),
+}
 
-            break only_positions_hashset.len()
+impl PathCanvas {
+    fn new(map: &Map, start: State) -> Self {
+        let mut cells = (0..map.rows)
+            .map(|x| {
+                (0..map.cols)
+                    .map(|y| if map.is_walkable(x, y) { '.' } else { '#' })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        cells[start.0][start.1] = start.2.glyph();
+        PathCanvas { cells, start: (start.0, start.1) }
+    }
+
+    /// Draws glyph at (x, y). The start position is always kept as is.
+    fn mark(&mut self, x: usize, y: usize, glyph: char) {
+        if (x, y) == self.start {
+            return;
         }
-    };
+        let cell = &mut self.cells[x][y];
+        *cell = match (*cell, glyph) {
+            ('.', g) => g,
+            ('|', '-') | ('-', '|') | (_, '+') | ('+', _) => '+',
+            (c, _) => c,
+        };
+    }
+
+    /// Draws the guard going from one state to the next, with a step or a turn.
+    fn draw_transition(&mut self, from: State, to: State) {
+        let (x, y, direction) = from;
+        if (to.0, to.1) == (x, y) {
+            self.mark(x, y, '+');
+        } else {
+            let glyph = match direction {
+                Direction::UP | Direction::DOWN => '|',
+                Direction::LEFT | Direction::RIGHT => '-',
+            };
+            self.mark(x, y, glyph);
+            self.mark(to.0, to.1, glyph);
+        }
+    }
+
+    /// Walks the guard one step (or turn) and draws it.
+    fn walk(&mut self, guard: &mut Guard, map: &Map) {
+        let from = (guard.x, guard.y, guard.direction);
+        guard.check_and_move(map);
+        if !guard.oob {
+            self.draw_transition(from, (guard.x, guard.y, guard.direction));
+        }
+    }
 
-    Ok(distinct_places)
+    /// Renders the canvas, with the guard drawn on top if given.
+    fn render(&self, guard: Option<&Guard>) -> String {
+        let mut out = String::new();
+        for (i, row) in self.cells.iter().enumerate() {
+            for (j, &c) in row.iter().enumerate() {
+                match guard {
+                    Some(g) if !g.oob && (g.x, g.y) == (i, j) => out.push(g.direction.glyph()),
+                    _ => out.push(c),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Walks the guard until it leaves the map or loops, and returns the canvas with its path.
+fn draw_path(map: &Map, start: State) -> PathCanvas {
+    let mut canvas = PathCanvas::new(map, start);
+    let mut guard = Guard::new(start.0, start.1, start.2);
+    while !guard.oob && !guard.in_loop {
+        canvas.walk(&mut guard, map);
+    }
+    canvas
 }
 
-fn check_loop_or_oob(map: &[[bool;130];130], mut guard: Guard) -> bool {
+/// Renders the map with the full path of the guard.
+pub fn d6_render_path(input_path: &str) -> Result<String, Error> {
+    let (map, starting_pos) = load_map(input_path)?;
+    Ok(draw_path(&map, starting_pos).render(None))
+}
+
+/// Calls on_frame with the map after every step (or turn) of the guard, starting before the
+/// first step.
+pub fn d6_animate<F: FnMut(&str)>(input_path: &str, mut on_frame: F) -> Result<(), Error> {
+    let (map, starting_pos) = load_map(input_path)?;
+    let mut canvas = PathCanvas::new(&map, starting_pos);
+    let mut guard = Guard::new(starting_pos.0, starting_pos.1, starting_pos.2);
+    on_frame(&canvas.render(Some(&guard)));
+    while !guard.oob && !guard.in_loop {
+        canvas.walk(&mut guard, &map);
+        on_frame(&canvas.render(Some(&guard)));
+    }
+    Ok(())
+}
+
+/// Renders the map with the path of the guard and every position where a new obstacle makes the
+/// guard loop marked 'O'.
+pub fn d6_render_loop_obstacles(input_path: &str, jobs: usize) -> Result<String, Error> {
+    let (map, starting_pos) = load_map(input_path)?;
+    let mut canvas = draw_path(&map, starting_pos);
+    for (x, y) in find_loop_obstacles(&map, starting_pos, jobs)? {
+        canvas.cells[x][y] = 'O';
+    }
+    Ok(canvas.render(None))
+}
+
+/// What happens to the guard. A step is either moving forward or turning in place.
+enum Outcome {
+    /// The guard leaves the map after steps steps, having visited distinct_cells positions
+    /// (the start included).
+    Exits { steps: usize, distinct_cells: usize },
+    /// The guard takes prefix_len steps, then repeats the same cycle_len steps forever.
+    /// cycle_states are the states of the guard on the cycle, in order.
+    Loops {
+        prefix_len: usize,
+        cycle_len: usize,
+        cycle_states: Vec<State>,
+    },
+}
+
+/// Walks the guard step by step from start, with obstacle added to the map if given, until it
+/// leaves the map or comes back to a state it has been in.
+fn check_loop_or_oob(map: &Map, start: State, obstacle: Option<(usize, usize)>) -> Outcome {
+    let (mut x, mut y, mut direction) = start;
+    // The step at which the guard first was in each state
+    let mut first_seen: HashMap<State, usize> = HashMap::new();
+    let mut history = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert((x, y));
     loop {
-        guard.check_and_move(&map);
-        if guard.oob {
-            break false
+        let state = (x, y, direction);
+        if let Some(&t) = first_seen.get(&state) {
+            return Outcome::Loops {
+                prefix_len: t,
+                cycle_len: history.len() - t,
+                cycle_states: history.split_off(t),
+            };
         }
-        if guard.in_loop {
-            break true
+        first_seen.insert(state, history.len());
+        history.push(state);
+        match map.step(x, y, direction) {
+            None => {
+                return Outcome::Exits {
+                    steps: history.len() - 1,
+                    distinct_cells: visited.len(),
+                }
+            }
+            Some((u, v)) if !map.is_walkable(u, v) || Some((u, v)) == obstacle => {
+                direction = direction.turn();
+            }
+            Some((u, v)) => {
+                (x, y) = (u, v);
+                visited.insert((u, v));
+            }
         }
     }
 }
 
-pub fn d6_part2_solution(input_path: &str) -> Result<usize, Error> {
-    // True if walkable
-    let (mut map, starting_pos) = load_map(input_path)?;
-    let (x, y) = starting_pos;
+/// The loop the guard gets stuck in once a new obstacle is added.
+pub struct LoopSize {
+    pub obstacle: (usize, usize),
+    /// Steps before the guard enters the loop.
+    pub prefix_len: usize,
+    /// Steps in one round of the loop.
+    pub cycle_len: usize,
+}
+
+/// Returns the loop for every position where a new obstacle makes the guard loop, in the order
+/// the guard first visits them.
+pub fn d6_loop_sizes(input_path: &str, jobs: usize) -> Result<Vec<LoopSize>, Error> {
+    let (map, starting_pos) = load_map(input_path)?;
+    find_loop_obstacles(&map, starting_pos, jobs)?
+        .into_iter()
+        .map(|obstacle| match check_loop_or_oob(&map, starting_pos, Some(obstacle)) {
+            Outcome::Loops { prefix_len, cycle_len, .. } => Ok(LoopSize {
+                obstacle,
+                prefix_len,
+                cycle_len,
+            }),
+            Outcome::Exits { .. } => Err(Error::Other(format!(
+                "The guard doesn't loop with an obstacle at {:?}.",
+                obstacle
+            ))),
+        })
+        .collect()
+}
 
-    // Could there be more reduction we can do? E.g. no need to check lots of walkable positions?
-    let mut will_loop_count = 0usize; 
-    for i in 0..130 {
-        for j in 0..130 {
-            // Walkable and not initial position
-            if map[i][j] && (i != x || j != y) {
-                map[i][j] = false;
-                will_loop_count += check_loop_or_oob(&map, Guard::new(x, y)) as usize;
-                map[i][j] = true; // return it back
-            } else {
-                continue; // skip existing obstacles
+/// Reports what happens to the guard once obstacle is added, if given. If the guard loops, the
+/// report has the map with the loop drawn on it and the new obstacle marked 'O'. Returns an error
+/// if the obstacle is out of the map, on the starting position or on an existing obstacle.
+fn cycle_report(
+    map: &Map,
+    start: State,
+    obstacle: Option<(usize, usize)>,
+) -> Result<String, Error> {
+    if let Some((x, y)) = obstacle {
+        if x >= map.rows || y >= map.cols {
+            return Err(Error::Other(format!(
+                "Obstacle ({}, {}) is out of the {}x{} map.",
+                x, y, map.rows, map.cols
+            )));
+        }
+        if (x, y) == (start.0, start.1) {
+            return Err(Error::Other(format!(
+                "Obstacle ({}, {}) is on the starting position of the guard.",
+                x, y
+            )));
+        }
+        if !map.is_walkable(x, y) {
+            return Err(Error::Other(format!("There is already an obstacle at ({}, {}).", x, y)));
+        }
+    }
+    let (prefix_len, cycle_len, cycle_states) = match check_loop_or_oob(map, start, obstacle) {
+        Outcome::Loops { prefix_len, cycle_len, cycle_states } => {
+            (prefix_len, cycle_len, cycle_states)
+        }
+        Outcome::Exits { steps, distinct_cells } => {
+            return Ok(format!(
+                "The guard leaves the map after {} steps, visiting {} distinct positions.\n",
+                steps, distinct_cells
+            ));
+        }
+    };
+    let mut canvas = PathCanvas::new(map, start);
+    if let Some((x, y)) = obstacle {
+        canvas.cells[x][y] = 'O';
+    }
+    // The last state goes back to the first one
+    for (i, &from) in cycle_states.iter().enumerate() {
+        canvas.draw_transition(from, cycle_states[(i + 1) % cycle_states.len()]);
+    }
+    Ok(format!(
+        "The guard loops after {} steps, repeating the same {} steps:\n{}",
+        prefix_len,
+        cycle_len,
+        canvas.render(None)
+    ))
+}
+
+/// Reports whether the guard leaves the map or loops once an obstacle is added at the given
+/// position, or without a new obstacle if not given. Returns an error if the position is out of
+/// the map, on the starting position or already an obstacle.
+pub fn d6_render_cycle(
+    input_path: &str,
+    obstacle: Option<(usize, usize)>,
+) -> Result<String, Error> {
+    let (map, starting_pos) = load_map(input_path)?;
+    cycle_report(&map, starting_pos, obstacle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+
+    const EXAMPLE: &str = "....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...
+";
+
+    /// Rotates the map by 90 degrees clockwise, turning the guard with it.
+    fn rotate(text: &str) -> String {
+        let rows = text.lines().map(|l| l.chars().collect::<Vec<_>>()).collect::<Vec<_>>();
+        let mut out = String::new();
+        for j in 0..rows[0].len() {
+            for row in rows.iter().rev() {
+                out.push(match Direction::from_glyph(row[j]) {
+                    Some(direction) => direction.turn().glyph(),
+                    None => row[j],
+                });
             }
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn example_in_every_orientation() {
+        let mut text = EXAMPLE.to_string();
+        for glyph in ['^', '>', 'v', '<'] {
+            let (map, start) = parse_map(&text).unwrap();
+            assert_eq!(start.2.glyph(), glyph);
+            assert_eq!((map.rows, map.cols), (10, 10));
+            assert_eq!(distinct_places(&map, start), 41);
+            assert_eq!(find_loop_obstacles(&map, start, 2).unwrap().len(), 6);
+            text = rotate(&text);
         }
     }
 
-    Ok(will_loop_count)
-}
\ No newline at end of file
+    #[test]
+    fn start_state() {
+        assert_eq!(parse_map("...\n.>.\n").unwrap().1, (1, 1, Direction::RIGHT));
+        assert_eq!(parse_map("#v\r\n..\r\n").unwrap().1, (0, 1, Direction::DOWN));
+        assert_eq!(parse_map("<").unwrap().1, (0, 0, Direction::LEFT));
+        let (map, start) = parse_map("..#..\n..^..\n").unwrap();
+        assert_eq!((map.rows, map.cols, start), (2, 5, (1, 2, Direction::UP)));
+    }
+
+    #[test]
+    fn bad_maps_are_rejected() {
+        for text in ["", "...\n...", "^.^", "^\nv", "<>\n..", "^..\n..", "..\n^.."] {
+            assert!(matches!(parse_map(text), Err(Error::Other(_))), "{:?}", text);
+        }
+    }
+
+    /// A random map with about one obstacle in density cells and the guard somewhere walkable.
+    fn random_map(rng: &mut Rng, rows: usize, cols: usize, density: usize) -> String {
+        let mut cells = (0..rows * cols)
+            .map(|_| if rng.below(density) == 0 { '#' } else { '.' })
+            .collect::<Vec<_>>();
+        let k = rng.below(rows * cols);
+        cells[k] = ['^', '>', 'v', '<'][rng.below(4)];
+        cells
+            .chunks(cols)
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    /// Where the guard stops walking straight from (x, y), one step at a time.
+    fn walk_straight(
+        map: &Map,
+        (mut x, mut y): (usize, usize),
+        direction: Direction,
+        obstacle: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        while let Some((u, v)) = map.step(x, y, direction) {
+            if !map.is_walkable(u, v) || (u, v) == obstacle {
+                return Some((x, y));
+            }
+            (x, y) = (u, v);
+        }
+        None
+    }
+
+    #[test]
+    fn jump_with_obstacle() {
+        let (map, _) = parse_map("..#..\n.....\n.....\n.....\n.....\n..^..\n.....\n").unwrap();
+        let jumps = JumpTable::new(&map);
+        let (up, left) = (Direction::UP, Direction::LEFT);
+        // No obstacle in the way
+        assert_eq!(jumps.jump(5, 2, up, (6, 2)), Some((1, 2)));
+        assert_eq!(jumps.jump(5, 2, up, (3, 3)), Some((1, 2)));
+        // Obstacle at the jump target
+        assert_eq!(jumps.jump(5, 2, up, (1, 2)), Some((2, 2)));
+        // Obstacle between the guard and the target
+        assert_eq!(jumps.jump(5, 2, up, (3, 2)), Some((4, 2)));
+        // Obstacle directly ahead, the guard turns in place
+        assert_eq!(jumps.jump(5, 2, up, (4, 2)), Some((5, 2)));
+        // The guard would leave the map
+        assert_eq!(jumps.jump(5, 2, left, (4, 0)), None);
+        assert_eq!(jumps.jump(5, 2, left, (5, 0)), Some((5, 1)));
+        assert_eq!(jumps.jump(5, 2, left, (5, 1)), Some((5, 2)));
+        // At the edge of the map
+        assert_eq!(jumps.jump(5, 0, left, (5, 1)), None);
+    }
+
+    #[test]
+    fn jump_matches_walking() {
+        let directions = [Direction::UP, Direction::RIGHT, Direction::DOWN, Direction::LEFT];
+        let mut rng = Rng::new(44);
+        for _ in 0..50 {
+            let (map, _) = parse_map(&random_map(&mut rng, 7, 9, 5)).unwrap();
+            let jumps = JumpTable::new(&map);
+            for k in 0..map.len() {
+                let (x, y) = (k / map.cols, k % map.cols);
+                for direction in directions {
+                    for o in 0..map.len() {
+                        let obstacle = (o / map.cols, o % map.cols);
+                        if obstacle == (x, y) {
+                            continue;
+                        }
+                        assert_eq!(
+                            jumps.jump(x, y, direction, obstacle),
+                            walk_straight(&map, (x, y), direction, obstacle),
+                            "{:?} {:?} {:?}",
+                            (x, y),
+                            direction,
+                            obstacle
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jumping_loop_check_matches_step_by_step() {
+        let mut rng = Rng::new(47);
+        let mut maps = vec![EXAMPLE.to_string()];
+        maps.extend((0..100).map(|_| random_map(&mut rng, 8, 8, 6)));
+        for text in maps {
+            let (map, start) = parse_map(&text).unwrap();
+            let jumps = JumpTable::new(&map);
+            let mut turns = StateSet::new(&map);
+            for o in 0..map.len() {
+                let obstacle = (o / map.cols, o % map.cols);
+                if obstacle == (start.0, start.1) || !map.is_walkable(obstacle.0, obstacle.1) {
+                    continue;
+                }
+                let loops = matches!(
+                    check_loop_or_oob(&map, start, Some(obstacle)),
+                    Outcome::Loops { .. }
+                );
+                assert_eq!(
+                    check_loop_with_obstacle(&jumps, start, obstacle, &mut turns),
+                    loops,
+                    "{}{:?}",
+                    text,
+                    obstacle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn path_candidates_find_every_loop_obstacle() {
+        let mut rng = Rng::new(45);
+        let mut maps = vec![EXAMPLE.to_string()];
+        maps.extend((0..200).map(|_| random_map(&mut rng, 8, 10, 6)));
+        for text in maps {
+            let (map, start) = parse_map(&text).unwrap();
+            // Try every position, one step at a time
+            let expected = (0..map.len())
+                .map(|o| (o / map.cols, o % map.cols))
+                .filter(|&(x, y)| (x, y) != (start.0, start.1) && map.is_walkable(x, y))
+                .filter(|&o| {
+                    matches!(check_loop_or_oob(&map, start, Some(o)), Outcome::Loops { .. })
+                })
+                .collect::<HashSet<_>>();
+            let found = find_loop_obstacles(&map, start, 3).unwrap();
+            assert_eq!(found.len(), expected.len(), "{}", text);
+            assert_eq!(found.into_iter().collect::<HashSet<_>>(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn loop_obstacles_dont_depend_on_jobs() {
+        let mut rng = Rng::new(46);
+        let mut maps = vec![EXAMPLE.to_string()];
+        maps.extend((0..200).map(|_| random_map(&mut rng, 16, 14, 9)));
+        for text in maps {
+            let (map, start) = parse_map(&text).unwrap();
+            let expected = find_loop_obstacles(&map, start, 1).unwrap();
+            for jobs in [2, 3, 8] {
+                assert_eq!(find_loop_obstacles(&map, start, jobs).unwrap(), expected, "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn state_set_clear() {
+        let (map, _) = parse_map("^..\n...").unwrap();
+        let mut set = StateSet::new(&map);
+        let a = (1, 2, Direction::LEFT);
+        let b = (0, 0, Direction::UP);
+        assert!(set.insert(a));
+        assert!(!set.insert(a));
+        assert!(set.insert(b));
+        set.clear();
+        assert!(set.insert(a));
+        assert!(!set.insert(a));
+    }
+
+    #[test]
+    fn state_set_generation_wraparound() {
+        let (map, _) = parse_map("^..\n...").unwrap();
+        let mut set = StateSet::new(&map);
+        let a = (1, 2, Direction::LEFT);
+        let b = (0, 1, Direction::DOWN);
+        set.generation = u32::MAX - 1;
+        assert!(set.insert(a));
+        set.clear();
+        assert_eq!(set.generation, u32::MAX);
+        assert!(set.insert(b));
+        assert!(!set.insert(b));
+        // Wraps around: nothing inserted before may look current again
+        set.clear();
+        assert_eq!(set.generation, 1);
+        assert!(set.insert(a));
+        assert!(set.insert(b));
+        assert!(!set.insert(a));
+        set.clear();
+        assert!(set.insert(a));
+    }
+
+    #[test]
+    fn outcome_without_obstacle() {
+        let real = load_map("inputs/d6_input.txt").unwrap();
+        for (map, start) in [parse_map(EXAMPLE).unwrap(), real] {
+            // Every call to check_and_move is a step, except the one that leaves the map
+            let mut guard = Guard::new(start.0, start.1, start.2);
+            let mut guard_steps = 0;
+            while !guard.oob {
+                guard.check_and_move(&map);
+                guard_steps += 1;
+            }
+            match check_loop_or_oob(&map, start, None) {
+                Outcome::Exits { steps, distinct_cells } => {
+                    assert_eq!(steps, guard_steps - 1);
+                    assert_eq!(distinct_cells, distinct_places(&map, start));
+                }
+                Outcome::Loops { .. } => panic!("the guard should leave the map"),
+            }
+        }
+        let (map, start) = parse_map(EXAMPLE).unwrap();
+        assert!(matches!(
+            check_loop_or_oob(&map, start, None),
+            Outcome::Exits { distinct_cells: 41, .. }
+        ));
+        let report = cycle_report(&map, start, None).unwrap();
+        assert!(report.starts_with("The guard leaves the map after"));
+    }
+
+    #[test]
+    fn outcome_with_loop() {
+        let (map, start) = parse_map(EXAMPLE).unwrap();
+        // Right next to the guard: it goes around once (5 + 4 + 5 + 4 moves and 4 turns) and
+        // is back where it started.
+        match check_loop_or_oob(&map, start, Some((6, 3))) {
+            Outcome::Loops { prefix_len, cycle_len, cycle_states } => {
+                assert_eq!((prefix_len, cycle_len), (0, 22));
+                assert_eq!(cycle_states.len(), 22);
+                assert_eq!(cycle_states[0], start);
+            }
+            Outcome::Exits { .. } => panic!("the guard should loop"),
+        }
+        // Up 5, right 4, down 5 and 3 turns, then left 2 to reach (6, 6) facing left after 19
+        // steps. From there, the guard goes around the block at (4, 2)-(6, 6) in 16 steps.
+        match check_loop_or_oob(&map, start, Some((7, 6))) {
+            Outcome::Loops { prefix_len, cycle_len, cycle_states } => {
+                assert_eq!((prefix_len, cycle_len), (19, 16));
+                assert_eq!(cycle_states[0], (6, 6, Direction::LEFT));
+            }
+            Outcome::Exits { .. } => panic!("the guard should loop"),
+        }
+        let report = cycle_report(&map, start, Some((7, 6))).unwrap();
+        assert!(report.starts_with("The guard loops after 19 steps, repeating the same 16 steps"));
+        assert!(report.contains("\n..+---+#..\n"), "{}", report);
+        assert!(report.contains("\n.#+-^-+...\n"), "{}", report);
+        assert!(report.contains(".O.#."), "{}", report);
+    }
+
+    #[test]
+    fn cycle_report_rejects_bad_obstacles() {
+        let (map, start) = parse_map(EXAMPLE).unwrap();
+        // Out of the map, the starting position and an existing obstacle
+        for obstacle in [(10, 0), (0, 10), (6, 4), (0, 4), (9, 6)] {
+            let report = cycle_report(&map, start, Some(obstacle));
+            assert!(matches!(report, Err(Error::Other(_))), "{:?}", obstacle);
+        }
+        assert!(cycle_report(&map, start, Some((0, 0))).is_ok());
+    }
+}
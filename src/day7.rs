@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::parse::parse_numbers;
 use std::fs::File;
 use std::io::Read;
 
@@ -72,17 +73,12 @@ pub fn parse_inputs(input_path: &str) -> Result<Vec<(isize, Vec<isize>)>, Error>
         let mut split = line.split(':');
         if let (Some(left), Some(right)) = (split.next(), split.next()) {
             let a = left.trim().parse::<isize>();
-            let b = right
-                .trim()
-                .split(' ')
-                .filter_map(|v| v.parse::<isize>().ok())
-                .collect::<Vec<_>>();
+            let b = parse_numbers::<isize>(right)?;
 
             // Line is correct
-            if a.is_ok() && b.len() > 0 {
-                outputs.push((a.unwrap(), b));
-            } else {
-                return Err(Error::Other("Input file is not correctly formatted.".into()))
+            match a {
+                Ok(a) if !b.is_empty() => outputs.push((a, b)),
+                _ => return Err(Error::Other("Input file is not correctly formatted.".into())),
             }
         }
     }
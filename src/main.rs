@@ -8,51 +8,392 @@ mod day5;
 mod day6;
 mod day7;
 mod error;
+mod parse;
+#[cfg(test)]
+mod test_util;
 
-use day1::{d1_part1_solution, d1_part2_solution};
-use day2::{d2_part1_solution, d2_part2_solution};
+use day1::{LocationId, LocationLists, SimilarityStrategy};
+use day2::{
+    d2_count_with_dampener, d2_diagnostics, d2_part1_solution, d2_part2_solution,
+    d2_removal_histogram, Diagnosis, SafetyRule,
+};
 use day3::{d3_part1_solution, d3_part2_solution};
-use day4::{d4_part1_solution, d4_part2_solution};
-use day5::{d5_part1_solution, d5_part2_solution};
-use day6::{d6_part1_solution, d6_part2_solution};
+use day4::{
+    d4_count_stencil, d4_direction_breakdown, d4_part1_solution, d4_part2_solution, d4_render,
+    RenderTarget,
+};
+use day5::{
+    d5_part1_solution, d5_part2_solution, d5_reorder_analysis, d5_rules_dot, d5_violations,
+};
+use day6::{
+    d6_animate, d6_loop_sizes, d6_part1_solution, d6_part2_solution, d6_render_cycle,
+    d6_render_loop_obstacles, d6_render_path,
+};
 use day7::{d7_part1_solution, d7_part2_solution};
 use error::Error;
 
-fn main() -> Result<(), Error> {
-    // let d1_part1 = d1_part1_solution("inputs/d1_input.txt")?;
-    // println!("D1 part 1 solution: {}", d1_part1.0);
-    // let d1_part2 = d1_part2_solution(d1_part1.1, d1_part1.2);
-    // println!("D1 part 2 solution: {}", d1_part2);
-
-    // let d2_part1 = d2_part1_solution("inputs/d2_input.txt")?;
-    // println!("D2 part 1 solution: {}", d2_part1);
-    // let d2_part2 = d2_part2_solution("inputs/d2_input.txt")?;
-    // println!("D2 part 2 solution: {}", d2_part2);
-
-    // let d3_part1 = d3_part1_solution("inputs/d3_input.txt")?;
-    // println!("D3 part 1 solution: {}", d3_part1);
-    // let d3_part2 = d3_part2_solution("inputs/d3_input.txt")?;
-    // println!("D3 part 2 solution: {}", d3_part2);
-
-    // let d4_part1 = d4_part1_solution("inputs/d4_input.txt")?;
-    // println!("D4 part 1 solution: {}", d4_part1);
-    // let d4_part2 = d4_part2_solution("inputs/d4_input.txt")?;
-    // println!("D4 part 2 solution: {}", d4_part2);
-
-    // let d5_part1 = d5_part1_solution("inputs/d5_input.txt")?;
-    // println!("D5 part 1 solution: {}", d5_part1);
-    // let d5_part2 = d5_part2_solution("inputs/d5_input.txt")?;
-    // println!("D5 part 2 solution: {}", d5_part2);
-
-    // let d6_part1 = d6_part1_solution("inputs/d6_input.txt")?;
-    // println!("D6 part 1 solution: {}", d6_part1);
-    // let d6_part2 = d6_part2_solution("inputs/d6_input.txt")?;
-    // println!("D6 part 2 solution: {}", d6_part2);
+/// Returns the value right after flag in args, if the flag is present.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
+
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|a| a == flag)
+}
+
+fn run_day1_with<T: LocationId>(args: &[String], input_path: &str) -> Result<(), Error> {
+    let strategy = match flag_value(args, "--similarity") {
+        None | Some("merge") => SimilarityStrategy::MergeCount,
+        Some("frequency") => SimilarityStrategy::FrequencyMap,
+        Some(other) => return Err(Error::Other(format!("Unknown strategy: {}", other))),
+    };
+    let lists = LocationLists::<T>::load(input_path)?;
+    let d1_part1 = lists.total_distance()?;
+    println!("D1 part 1 solution: {}", d1_part1);
+    let d1_part2 = lists.similarity_score(strategy)?;
+    println!("D1 part 2 solution: {}", d1_part2);
+
+    if has_flag(args, "--stats") {
+        let top_n = match flag_value(args, "--top") {
+            Some(n) => n.parse::<usize>().map_err(Error::ParseIntError)?,
+            None => 5,
+        };
+        let stats = lists.stats(top_n)?;
+        println!("{:<16} | {:>8} | {:>8}", "", "Left", "Right");
+        println!("{:<16} | {:>8} | {:>8}", "Count", stats.c1.count, stats.c2.count);
+        println!("{:<16} | {:>8} | {:>8}", "Distinct ids", stats.c1.distinct, stats.c2.distinct);
+        println!(
+            "{:<16} | {:>8} | {:>8}",
+            "Duplicated ids", stats.c1.duplicated_ids, stats.c2.duplicated_ids
+        );
+        println!("Ids in both lists: {}", stats.overlap);
+        match stats.median_gap {
+            Some(median) => println!("Median gap: {}", median),
+            None => println!("Median gap: n/a"),
+        }
+        println!("Largest gaps:");
+        for (left, right, gap) in stats.largest_gaps {
+            println!("    {} - {} = {}", left, right, gap);
+        }
+        println!("Most similar ids:");
+        for (id, score) in stats.most_similar {
+            println!("    {}: {}", id, score);
+        }
+    }
+    Ok(())
+}
+
+/// Options:
+///     --input path: read the lists from path instead of the AOC input.
+///     --id-type usize|u64|u128|i64: the integer type of the ids. Defaults to usize.
+///     --similarity frequency|merge: the strategy for the similarity score. Defaults to merge.
+///     --stats: print descriptive statistics of the two lists.
+///     --top n: the number of largest gaps and most similar ids in --stats. Defaults to 5.
+fn run_day1(args: &[String]) -> Result<(), Error> {
+    let input_path = flag_value(args, "--input").unwrap_or("inputs/d1_input.txt");
+    match flag_value(args, "--id-type") {
+        None | Some("usize") => run_day1_with::<usize>(args, input_path),
+        Some("u64") => run_day1_with::<u64>(args, input_path),
+        Some("u128") => run_day1_with::<u128>(args, input_path),
+        Some("i64") => run_day1_with::<i64>(args, input_path),
+        Some(other) => Err(Error::Other(format!("Unknown id type: {}", other))),
+    }
+}
+
+/// Builds the day 2 safety rule from the options, starting from the puzzle's rule.
+fn parse_safety_rule(args: &[String]) -> Result<SafetyRule, Error> {
+    let mut rule = SafetyRule::default();
+    if let Some(v) = flag_value(args, "--min-step") {
+        rule.min_step = v.parse::<i32>().map_err(Error::ParseIntError)?;
+    }
+    if let Some(v) = flag_value(args, "--max-step") {
+        rule.max_step = v.parse::<i32>().map_err(Error::ParseIntError)?;
+    }
+    rule.allow_flat = has_flag(args, "--allow-flat");
+    if let Some(v) = flag_value(args, "--direction") {
+        rule.direction = match v {
+            "increasing" => day2::Direction::Increasing,
+            "decreasing" => day2::Direction::Decreasing,
+            "either" => day2::Direction::Either,
+            _ => return Err(Error::Other(format!("Unknown direction: {}", v))),
+        };
+    }
+    rule.validate()?;
+    Ok(rule)
+}
+
+/// Options:
+///     --tolerance k: count the reports that are safe when up to k bad levels are removed.
+///     --histogram: print how many reports need 0, 1, 2, ... removals to be safe.
+///     --diagnose: print a table with the diagnosis of every report.
+///     --min-step n, --max-step n, --allow-flat, --direction increasing|decreasing|either:
+///         override the safety rule used by --tolerance, --histogram and --diagnose.
+fn run_day2(args: &[String]) -> Result<(), Error> {
+    let input_path = "inputs/d2_input.txt";
+    let d2_part1 = d2_part1_solution(input_path)?;
+    println!("D2 part 1 solution: {}", d2_part1);
+    let d2_part2 = d2_part2_solution(input_path)?;
+    println!("D2 part 2 solution: {}", d2_part2);
+
+    let rule = parse_safety_rule(args)?;
+    if let Some(k) = flag_value(args, "--tolerance") {
+        let k = k.parse::<usize>().map_err(Error::ParseIntError)?;
+        let count = d2_count_with_dampener(input_path, k, &rule)?;
+        println!("D2 safe reports with tolerance {}: {}", k, count);
+    }
+
+    if has_flag(args, "--histogram") {
+        println!("{:>8} | {:>7}", "Removals", "Reports");
+        for (k, count) in d2_removal_histogram(input_path, &rule)?.into_iter().enumerate() {
+            println!("{:>8} | {:>7}", k, count);
+        }
+    }
+
+    if has_flag(args, "--diagnose") {
+        println!(
+            "{:>6} | {:<18} | {:>5} | {:<16} | Levels",
+            "Report", "Status", "Index", "Reason"
+        );
+        for (i, (levels, diagnosis)) in d2_diagnostics(input_path, &rule)?.into_iter().enumerate() {
+            let (status, index, reason) = match diagnosis {
+                Diagnosis::Safe => ("safe", String::new(), String::new()),
+                Diagnosis::SafeWithDampener { removed } => {
+                    ("safe with dampener", removed.to_string(), String::new())
+                }
+                Diagnosis::Unsafe { window, reason } => {
+                    ("unsafe", window.to_string(), reason.to_string())
+                }
+            };
+            println!(
+                "{:>6} | {:<18} | {:>5} | {:<16} | {:?}",
+                i, status, index, reason, levels
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_day3(_args: &[String]) -> Result<(), Error> {
+    let d3_part1 = d3_part1_solution("inputs/d3_input.txt")?;
+    println!("D3 part 1 solution: {}", d3_part1);
+    let d3_part2 = d3_part2_solution("inputs/d3_input.txt")?;
+    println!("D3 part 2 solution: {}", d3_part2);
+    Ok(())
+}
+
+/// Options:
+///     --stencil pattern: count the matches of a pattern, under all rotations and reflections.
+///         Rows are separated by '/' and '.' matches anything, e.g. ".M./MAS/.S.".
+///     --render [xmas|x-mas]: print the grid with only the letters of the matches kept, instead
+///         of the solutions. Defaults to xmas.
+///     --color: with --render, colour every match by its direction.
+///     --directions [word]: print the matches of word by direction. Defaults to XMAS.
+fn run_day4(args: &[String]) -> Result<(), Error> {
+    let input_path = "inputs/d4_input.txt";
+    if has_flag(args, "--render") {
+        let target = match flag_value(args, "--render") {
+            Some("x-mas") => RenderTarget::Crosses,
+            Some("xmas") | None => RenderTarget::Words,
+            Some(other) if other.starts_with("--") => RenderTarget::Words,
+            Some(other) => return Err(Error::Other(format!("Unknown render target: {}", other))),
+        };
+        print!("{}", d4_render(input_path, target, has_flag(args, "--color"))?);
+        return Ok(());
+    }
+
+    let d4_part1 = d4_part1_solution(input_path)?;
+    println!("D4 part 1 solution: {}", d4_part1);
+    let d4_part2 = d4_part2_solution(input_path)?;
+    println!("D4 part 2 solution: {}", d4_part2);
 
+    if let Some(pattern) = flag_value(args, "--stencil") {
+        let count = d4_count_stencil(input_path, &pattern.replace('/', "\n"))?;
+        println!("D4 matches of {}: {}", pattern, count);
+    }
+
+    if has_flag(args, "--directions") {
+        let word = match flag_value(args, "--directions") {
+            Some(w) if !w.starts_with("--") => w,
+            _ => "XMAS",
+        };
+        let breakdown = d4_direction_breakdown(input_path, word)?;
+        println!("{:<11} | {:>7}", "Direction", "Matches");
+        for (direction, count) in &breakdown.counts {
+            println!("{:<11} | {:>7}", format!("{:?}", direction), count);
+        }
+        let total: usize = breakdown.counts.iter().map(|(_, n)| n).sum();
+        println!("{:<11} | {:>7}", "Total", total);
+        if breakdown.palindrome {
+            println!("{} is a palindrome, every occurrence is counted twice.", word);
+        }
+        println!(
+            "Pairs of matches covering the same cells: {}",
+            breakdown.same_cell_pairs
+        );
+    }
+    Ok(())
+}
+
+/// Options:
+///     --dot [update-index]: print the rules as a Graphviz DOT digraph instead of the solutions.
+///         With an update index, only the pages of that update are kept and the rules it
+///         violates are drawn in red.
+///     --violations: list every invalid update with the rules it breaks.
+///     --reorder: print how far every invalid update is from a valid order.
+fn run_day5(args: &[String]) -> Result<(), Error> {
+    let input_path = "inputs/d5_input.txt";
+    if has_flag(args, "--dot") {
+        let update_index = match flag_value(args, "--dot") {
+            Some(k) if !k.starts_with("--") => {
+                Some(k.parse::<usize>().map_err(Error::ParseIntError)?)
+            }
+            _ => None,
+        };
+        print!("{}", d5_rules_dot(input_path, update_index)?);
+        return Ok(());
+    }
+
+    let d5_part1 = d5_part1_solution(input_path)?;
+    println!("D5 part 1 solution: {}", d5_part1);
+    let d5_part2 = d5_part2_solution(input_path)?;
+    println!("D5 part 2 solution: {}", d5_part2);
+
+    if has_flag(args, "--violations") {
+        let invalid = d5_violations(input_path)?;
+        println!("{} invalid updates", invalid.len());
+        for update in invalid {
+            println!("Update {}: {:?}", update.index, update.page_nums);
+            for v in update.violations {
+                println!("    {}", v);
+            }
+        }
+    }
+
+    if has_flag(args, "--reorder") {
+        println!(
+            "{:>6} | {:>5} | {:>11} | {:>6} | Pages",
+            "Update", "Swaps", "Moved pages", "Unique"
+        );
+        for a in d5_reorder_analysis(input_path)? {
+            // Too many pages or valid orders to find the swaps
+            let swaps = a.min_adjacent_swaps.map_or("n/a".to_string(), |s| s.to_string());
+            let unique = a.unique_order.map_or("n/a".to_string(), |u| u.to_string());
+            println!(
+                "{:>6} | {:>5} | {:>11} | {:>6} | {:?}",
+                a.index, swaps, a.min_moved_pages, unique, a.page_nums
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Options:
+///     --jobs n: the number of threads for part 2. Defaults to the available parallelism.
+///     --render: print the path of the guard, then every position where a new obstacle makes
+///         the guard loop marked 'O', instead of the solutions.
+///     --animate: print the map after every step of the guard instead of the solutions.
+///     --delay ms: with --animate, wait ms milliseconds between frames. Defaults to 0.
+///     --cycle [row,col]: print whether the guard leaves the map or loops once an obstacle is
+///         added at (row, col), or without a new obstacle if not given, with the loop drawn if it
+///         loops, instead of the solutions.
+///     --loops: print the loop for every position where a new obstacle makes the guard loop.
+fn run_day6(args: &[String]) -> Result<(), Error> {
+    let input_path = "inputs/d6_input.txt";
+    let jobs = match flag_value(args, "--jobs") {
+        Some(n) => n.parse::<usize>().map_err(Error::ParseIntError)?,
+        // Falls back to 1 thread if the parallelism can't be queried
+        None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    };
+
+    if has_flag(args, "--render") {
+        println!("{}", d6_render_path(input_path)?);
+        print!("{}", d6_render_loop_obstacles(input_path, jobs)?);
+        return Ok(());
+    }
+
+    if has_flag(args, "--animate") {
+        let delay = match flag_value(args, "--delay") {
+            Some(ms) => ms.parse::<u64>().map_err(Error::ParseIntError)?,
+            None => 0,
+        };
+        let mut step = 0usize;
+        return d6_animate(input_path, |frame| {
+            println!("Step {}:\n{}", step, frame);
+            step += 1;
+            std::thread::sleep(std::time::Duration::from_millis(delay));
+        });
+    }
+
+    if has_flag(args, "--cycle") {
+        let obstacle = match flag_value(args, "--cycle") {
+            Some(pos) if !pos.starts_with("--") => {
+                let (row, col) = pos.split_once(',').ok_or_else(|| {
+                    Error::Other(format!("Expected --cycle row,col, got {}", pos))
+                })?;
+                Some((
+                    row.trim().parse::<usize>().map_err(Error::ParseIntError)?,
+                    col.trim().parse::<usize>().map_err(Error::ParseIntError)?,
+                ))
+            }
+            _ => None,
+        };
+        print!("{}", d6_render_cycle(input_path, obstacle)?);
+        return Ok(());
+    }
+
+    let d6_part1 = d6_part1_solution(input_path)?;
+    println!("D6 part 1 solution: {}", d6_part1);
+    let d6_part2 = d6_part2_solution(input_path, jobs)?;
+    println!("D6 part 2 solution: {}", d6_part2);
+
+    if has_flag(args, "--loops") {
+        println!("{:>10} | {:>6} | {:>5}", "Obstacle", "Prefix", "Cycle");
+        for l in d6_loop_sizes(input_path, jobs)? {
+            let (row, col) = l.obstacle;
+            let obstacle = format!("{},{}", row, col);
+            println!("{:>10} | {:>6} | {:>5}", obstacle, l.prefix_len, l.cycle_len);
+        }
+    }
+    Ok(())
+}
+
+fn run_day7(_args: &[String]) -> Result<(), Error> {
     let d7_part1 = d7_part1_solution("inputs/d7_input.txt")?;
     println!("D7 part 1 solution: {}", d7_part1);
     let d7_part2 = d7_part2_solution("inputs/d7_input.txt")?;
     println!("D7 part 2 solution: {}", d7_part2);
-
     Ok(())
 }
+
+/// Usage: aoc_2024 [day] [options]
+/// Runs every day if no day is given. See the run_dayN functions for the options of each day.
+fn main() -> Result<(), Error> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (day, options) = match args.split_first() {
+        Some((day, options)) => (Some(day.as_str()), options),
+        None => (None, &args[..]),
+    };
+
+    match day {
+        None => {
+            run_day1(options)?;
+            run_day2(options)?;
+            run_day3(options)?;
+            run_day4(options)?;
+            run_day5(options)?;
+            run_day6(options)?;
+            run_day7(options)
+        }
+        Some("1") => run_day1(options),
+        Some("2") => run_day2(options),
+        Some("3") => run_day3(options),
+        Some("4") => run_day4(options),
+        Some("5") => run_day5(options),
+        Some("6") => run_day6(options),
+        Some("7") => run_day7(options),
+        Some(other) => Err(Error::Other(format!("Unknown day: {}", other))),
+    }
+}
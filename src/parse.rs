@@ -0,0 +1,80 @@
+/// Parsing helpers shared by the days.
+use crate::error::Error;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// Parses every number in line. Numbers can be separated by any amount of whitespace
+/// (spaces, tabs, trailing whitespace). Returns a ParseIntError on the first token that
+/// is not a number.
+pub fn parse_numbers<T>(line: &str) -> Result<Vec<T>, Error>
+where
+    T: FromStr<Err = ParseIntError>,
+{
+    line.split_whitespace()
+        .map(|s| s.parse::<T>().map_err(Error::ParseIntError))
+        .collect()
+}
+
+/// Splits text into sections separated by one or more blank lines. Each section is returned as
+/// its lines, without the line endings (\n or \r\n) and trailing whitespace. Blank lines at the
+/// start or end of text are ignored.
+pub fn split_sections(text: &str) -> Vec<Vec<&str>> {
+    let mut sections = Vec::new();
+    let mut current = Vec::new();
+    for line in text.lines().map(|l| l.trim_end()) {
+        if line.is_empty() {
+            if !current.is_empty() {
+                sections.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        sections.push(current);
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_numbers_any_whitespace() {
+        assert_eq!(parse_numbers::<i32>("7 6 4 2 1").unwrap(), vec![7, 6, 4, 2, 1]);
+        assert_eq!(parse_numbers::<i32>("7\t6\t4").unwrap(), vec![7, 6, 4]);
+        assert_eq!(parse_numbers::<i32>("3   4").unwrap(), vec![3, 4]);
+        assert_eq!(parse_numbers::<i32>(" 1  2 \t 3 \r").unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_numbers::<i32>("-1 2").unwrap(), vec![-1, 2]);
+        assert!(parse_numbers::<i32>("").unwrap().is_empty());
+        assert!(parse_numbers::<i32>(" \t ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_numbers_rejects_non_numbers() {
+        assert!(matches!(parse_numbers::<i32>("1 x 3"), Err(Error::ParseIntError(_))));
+        assert!(matches!(parse_numbers::<i32>("1,2"), Err(Error::ParseIntError(_))));
+        assert!(matches!(parse_numbers::<u64>("1 -2"), Err(Error::ParseIntError(_))));
+    }
+
+    #[test]
+    fn split_sections_on_blank_lines() {
+        let expected = vec![vec!["47|53", "97|13"], vec!["75,47"]];
+        assert_eq!(split_sections("47|53\n97|13\n\n75,47\n"), expected);
+        assert_eq!(split_sections("47|53\r\n97|13\r\n\r\n75,47\r\n"), expected);
+        assert_eq!(split_sections("47|53 \n97|13\t\n\n75,47  "), expected);
+        assert_eq!(split_sections("47|53\n97|13\n\n\n\n75,47"), expected);
+        assert_eq!(split_sections("47|53\n97|13\n \t\n75,47"), expected);
+        assert_eq!(split_sections("47|53\r\n97|13\r\n  \r\n\r\n75,47"), expected);
+    }
+
+    #[test]
+    fn split_sections_ignores_outer_blank_lines() {
+        let expected = vec![vec!["1"], vec!["2"]];
+        assert_eq!(split_sections("\n\n1\n\n2\n\n\n"), expected);
+        assert_eq!(split_sections("  \r\n1\r\n\r\n2\r\n \r\n"), expected);
+        assert!(split_sections("").is_empty());
+        assert!(split_sections("\n \n\t\n").is_empty());
+    }
+}